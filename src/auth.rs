@@ -0,0 +1,225 @@
+use config::{self, AuthMode};
+use crypto::{self, constant_time_eq};
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How the bot decides that an admin is who they say they are.
+pub enum Authorization {
+    /// Anyone using an admin's nick is that admin.
+    Nick,
+
+    /// Admins must `.auth <password>` over PM to open a session.
+    Password(Authenticator),
+}
+
+impl Authorization {
+    pub fn with_config(auth: Option<&config::Auth>) -> Authorization {
+        let auth = match auth {
+            None => return Authorization::Nick,
+            Some(auth) => auth,
+        };
+
+        match auth.mode {
+            AuthMode::Nick => Authorization::Nick,
+
+            // read_config refuses to load a password config without these.
+            AuthMode::Password => Authorization::Password(Authenticator::new(
+                auth.salt.as_ref().expect("password auth requires a salt"),
+                auth.hash.as_ref().expect("password auth requires a hash"),
+                Duration::from_secs(auth.session_timeout),
+            )),
+        }
+    }
+}
+
+/// How many wrong passwords a sender gets before they have to wait.
+const FREE_ATTEMPTS: u32 = 3;
+
+/// How long the first lockout lasts. Each failure after that doubles it, up to an hour.
+const LOCKOUT_SECS: u64 = 60;
+const MAX_LOCKOUT_SECS: u64 = 60 * 60;
+
+/// Failures from everyone put together, within `GLOBAL_WINDOW_SECS`, before nobody gets to try;
+/// otherwise a change of nick would buy a fresh set of guesses.
+const GLOBAL_ATTEMPTS: usize = 10;
+const GLOBAL_WINDOW_SECS: u64 = 10 * 60;
+
+/// How a `.auth` went.
+#[derive(Debug, PartialEq)]
+pub enum Login {
+    Accepted,
+    Rejected,
+    /// Too many wrong passwords lately; the password wasn't even checked.
+    LockedOut,
+}
+
+/// Password-based authentication for admin commands.
+///
+/// A successful `.auth <password>` opens a session for the sender. The session lapses once the
+/// sender hasn't been seen--speaking, or using a command--for `session_timeout` seconds.
+///
+/// Sessions are keyed on the sender exactly as eirsee names it, which is only ever the nick, so
+/// the watcher ends a session whenever it sees that nick join or leave a channel. Whoever takes
+/// the nick after someone quits could pick the session up before it lapses, which is why the
+/// timeout has to be short.
+pub struct Authenticator {
+    salt: String,
+    hash: String,
+    timeout: Duration,
+    sessions: RefCell<HashMap<String, Instant>>,
+    /// Wrong passwords in a row for each sender, and when the last one was.
+    failures: RefCell<HashMap<String, (u32, Instant)>>,
+    /// When each recent wrong password was, whoever it came from.
+    recent_failures: RefCell<Vec<Instant>>,
+}
+
+impl Authenticator {
+    pub fn new(salt: &str, hash: &str, timeout: Duration) -> Authenticator {
+        Authenticator {
+            salt: salt.to_owned(),
+            hash: hash.to_lowercase(),
+            timeout: timeout,
+            sessions: RefCell::new(HashMap::new()),
+            failures: RefCell::new(HashMap::new()),
+            recent_failures: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Attempt to open a session for `sender`, unless they (or everyone) have been getting the
+    /// password wrong too often lately.
+    pub fn login(&self, sender: &str, password: &str) -> Login {
+        if self.locked_out(sender) {
+            return Login::LockedOut;
+        }
+
+        if !constant_time_eq(hash_password(&self.salt, password).as_bytes(), self.hash.as_bytes()) {
+            let mut failures = self.failures.borrow_mut();
+            let count = failures.get(sender).map_or(0, |&(count, _)| count) + 1;
+            failures.insert(sender.to_owned(), (count, Instant::now()));
+            self.recent_failures.borrow_mut().push(Instant::now());
+            return Login::Rejected;
+        }
+
+        self.failures.borrow_mut().remove(sender);
+        self.sessions.borrow_mut().insert(sender.to_owned(), Instant::now());
+        Login::Accepted
+    }
+
+    fn locked_out(&self, sender: &str) -> bool {
+        let window = Duration::from_secs(GLOBAL_WINDOW_SECS);
+        let mut recent = self.recent_failures.borrow_mut();
+        recent.retain(|failure| failure.elapsed() < window);
+        if recent.len() >= GLOBAL_ATTEMPTS {
+            return true;
+        }
+
+        match self.failures.borrow().get(sender) {
+            Some(&(count, last)) if count >= FREE_ATTEMPTS => {
+                let doublings = cmp::min(count - FREE_ATTEMPTS, 6);
+                let lockout = cmp::min(LOCKOUT_SECS << doublings, MAX_LOCKOUT_SECS);
+                last.elapsed() < Duration::from_secs(lockout)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn logout(&self, sender: &str) {
+        self.sessions.borrow_mut().remove(sender);
+    }
+
+    /// The sender just said something, so a session of theirs keeps going. One that has already
+    /// lapsed stays lapsed.
+    pub fn seen(&self, sender: &str) {
+        self.is_authenticated(sender);
+    }
+
+    /// Checks for a live session, refreshing its idle timer if there is one.
+    pub fn is_authenticated(&self, sender: &str) -> bool {
+        let mut sessions = self.sessions.borrow_mut();
+        let live = sessions.get(sender).map_or(false, |last| last.elapsed() < self.timeout);
+
+        if live {
+            sessions.insert(sender.to_owned(), Instant::now());
+        } else {
+            sessions.remove(sender);
+        }
+
+        live
+    }
+}
+
+/// Hex-encoded SHA-256 of the salt followed by the password.
+///
+/// This is the value expected in `[bot.auth].hash`; it can be produced with something like
+/// `printf '%s' "$SALT$PASSWORD" | sha256sum`.
+pub fn hash_password(salt: &str, password: &str) -> String {
+    crypto::hex(&crypto::sha256(&[salt.as_bytes(), password.as_bytes()]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Authenticator, Login, hash_password};
+    use std::time::Duration;
+
+    #[test]
+    fn hash_matches_sha256_of_salted_password() {
+        // printf '%s' 'saltpassword' | sha256sum
+        assert_eq!(
+            "13601bda4ea78e55a07b98866d2be6be0744e3866f13c00c811cab608a28f322",
+            hash_password("salt", "password")
+        );
+    }
+
+    #[test]
+    fn correct_password_opens_session() {
+        let auth = authenticator(60);
+
+        assert!(!auth.is_authenticated("admin"));
+        assert_eq!(Login::Accepted, auth.login("admin", "password"));
+        assert!(auth.is_authenticated("admin"));
+    }
+
+    #[test]
+    fn wrong_password_is_rejected() {
+        let auth = authenticator(60);
+
+        assert_eq!(Login::Rejected, auth.login("admin", "hunter2"));
+        assert!(!auth.is_authenticated("admin"));
+    }
+
+    #[test]
+    fn idle_sessions_expire() {
+        let auth = authenticator(0);
+
+        assert_eq!(Login::Accepted, auth.login("admin", "password"));
+        assert!(!auth.is_authenticated("admin"));
+    }
+
+    #[test]
+    fn repeated_failures_lock_the_sender_out() {
+        let auth = authenticator(60);
+        for _ in 0..3 {
+            assert_eq!(Login::Rejected, auth.login("admin", "hunter2"));
+        }
+
+        // Even the right password has to wait now, but nobody else does.
+        assert_eq!(Login::LockedOut, auth.login("admin", "password"));
+        assert_eq!(Login::Accepted, auth.login("boss", "password"));
+    }
+
+    #[test]
+    fn changing_nick_doesnt_buy_more_guesses() {
+        let auth = authenticator(60);
+        for n in 0..10 {
+            assert_eq!(Login::Rejected, auth.login(&format!("guess{}", n), "hunter2"));
+        }
+
+        assert_eq!(Login::LockedOut, auth.login("admin", "password"));
+    }
+
+    fn authenticator(timeout: u64) -> Authenticator {
+        Authenticator::new("salt", &hash_password("salt", "password"), Duration::from_secs(timeout))
+    }
+}
@@ -17,11 +17,68 @@ pub enum Command {
     JoinChannel(String),
     LeaveChannel(String),
 
+    // authentication
+    Authenticate(String),
+    Logout,
+
     // admin options
+    AddAdmin(String),
+    RemoveAdmin(String),
+    ListAdmins,
     SetTopic(String),
-    SetGreeting(String),
-    ListMessages,
-    Kill,
+    AddGreeting(NewGreeting),
+    RemoveGreeting(usize),
+    ListGreetings,
+    ListMessages(Vec<String>),
+    ListDeadLetters,
+    Kill(Option<String>),
+}
+
+/// A greeting as given to `.greet add [filter=<regex>] [passthru] <message>`. The filter has not
+/// been compiled yet.
+pub struct NewGreeting {
+    pub filter: Option<String>,
+    pub passthru: bool,
+    pub message: String,
+}
+
+/// Who may run a command. Admins may do anything trusted users can, and so on.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Permission {
+    Public,
+    Trusted,
+    Admin,
+}
+
+impl Command {
+    pub fn permission(&self) -> Permission {
+        match *self {
+            Command::Chuck
+            | Command::Cookie
+            | Command::ListCommands
+            | Command::Quote(_)
+            | Command::QuoteCategories
+            | Command::Roll(_)
+            | Command::Authenticate(_)
+            | Command::Logout => Permission::Public,
+
+            Command::SetTopic(_)
+            | Command::AddGreeting(_)
+            | Command::RemoveGreeting(_)
+            | Command::ListGreetings => Permission::Trusted,
+
+            Command::SetNick(_)
+            | Command::SetDebug(_)
+            | Command::JoinChannel(_)
+            | Command::LeaveChannel(_)
+            | Command::AddAdmin(_)
+            | Command::RemoveAdmin(_)
+            | Command::ListAdmins
+            | Command::ListMessages(_)
+            | Command::ListDeadLetters
+            | Command::Kill(_) => Permission::Admin,
+        }
+    }
 }
 
 impl FromStr for Command {
@@ -46,17 +103,59 @@ impl FromStr for Command {
             [".join", channel] => Ok(Command::JoinChannel(channel.to_owned())),
             [".leave", channel] => Ok(Command::LeaveChannel(channel.to_owned())),
 
+            // authentication
+            // Passphrases can have spaces in them.
+            [".auth", _, _..] => Ok(Command::Authenticate(s.trim()[".auth".len()..].trim().to_owned())),
+            [".logout"] => Ok(Command::Logout),
+
             // admin options
+            [".admin", "add", mask] => Ok(Command::AddAdmin(mask.to_owned())),
+            [".admin", "remove", mask] => Ok(Command::RemoveAdmin(mask.to_owned())),
+            [".admin", "list"] => Ok(Command::ListAdmins),
             [".topic", _..] => Ok(Command::SetTopic(s.replace(".topic ", ""))),
-            [".greet", _..] => Ok(Command::SetGreeting(s.replace(".greet ", ""))),
-            [".list-messages"] | [".listmessages"] | [".messages"] => Ok(Command::ListMessages),
-            [".kill"] => Ok(Command::Kill),
+            [".greet", "add", ref args..] => parse_greeting(args).map(Command::AddGreeting).ok_or(()),
+            [".greet", "remove", n] => n.parse().map(Command::RemoveGreeting).map_err(|_| ()),
+            [".greet", "list"] => Ok(Command::ListGreetings),
+            [".list-messages", ref args..] | [".listmessages", ref args..] | [".messages", ref args..] => {
+                Ok(Command::ListMessages(args.iter().map(|&arg| arg.to_owned()).collect()))
+            }
+            [".dead-letters"] | [".deadletters"] => Ok(Command::ListDeadLetters),
+            [".kill"] => Ok(Command::Kill(None)),
+            [".kill", _..] => Ok(Command::Kill(Some(s.replace(".kill ", "")))),
 
             _ => Err(()),
         }
     }
 }
 
+fn parse_greeting(args: &[&str]) -> Option<NewGreeting> {
+    let mut idx = 0;
+
+    let filter = match args.get(idx) {
+        Some(arg) if arg.starts_with("filter=") => {
+            idx += 1;
+            Some(arg["filter=".len()..].to_owned())
+        }
+        _ => None,
+    };
+
+    let passthru = args.get(idx) == Some(&"passthru");
+    if passthru {
+        idx += 1;
+    }
+
+    let message = args[idx..].join(" ");
+    if message.is_empty() {
+        return None;
+    }
+
+    Some(NewGreeting {
+        filter: filter,
+        passthru: passthru,
+        message: message,
+    })
+}
+
 #[inline]
 fn create_dice(s: &[&str]) -> Vec<Dice> {
     let dice: Vec<_> = s.iter().flat_map(|s| s.parse().ok()).collect();
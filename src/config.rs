@@ -1,4 +1,10 @@
+use chrono::Weekday;
+use chrono_tz::Tz;
 use greetings::Greeting;
+use mask::Mask;
+use regex::Regex;
+use state::State;
+use std::collections::HashMap;
 use serde::Deserialize;
 use std::fs::File;
 use std::fs;
@@ -7,16 +13,108 @@ use toml::{self, Value};
 
 #[derive(Deserialize)]
 pub struct Bot {
-    pub admin: Vec<String>,
+    pub admin: Vec<Mask>,
+    #[serde(default)]
+    pub trusted: Vec<Mask>,
+    #[serde(default)]
+    pub explain_denials: bool,
     pub message_frequency: u64,
-    pub watch_list: Vec<String>,
+    pub watch_list: Vec<Mask>,
+    pub auth: Option<Auth>,
+
+    // Where changes made at runtime (e.g. via `.admin add`) are kept.
+    #[serde(default = "default_state_path")]
+    pub state: String,
+}
+
+fn default_state_path() -> String {
+    String::from("state.toml")
+}
+
+/// Admin authentication. When this section is absent, admins are recognized by nick alone.
+#[derive(Clone, Deserialize)]
+pub struct Auth {
+    #[serde(default)]
+    pub mode: AuthMode,
+    pub salt: Option<String>,
+    pub hash: Option<String>,
+
+    // How long a password session lasts without its nick being seen.
+    pub session_timeout: u64,
+}
+
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    Nick,
+    Password,
+}
+
+impl Default for AuthMode {
+    fn default() -> Self {
+        AuthMode::Password
+    }
 }
 
 #[derive(Clone, Deserialize)]
 pub struct Server {
     pub address: String,
-    pub channel: String,
+
+    // A single channel can still be configured the old way, with `channel` and
+    // `[[server.greetings]]`; it comes ahead of anything in `[[server.channels]]`.
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub greetings: Vec<Greeting>,
+
+    #[serde(default)]
+    pub channels: Vec<Channel>,
+}
+
+impl Server {
+    /// Every configured channel. The first one is home: it's the one we connect to.
+    pub fn channels(&self) -> Vec<Channel> {
+        let mut channels = Vec::new();
+
+        if let Some(ref name) = self.channel {
+            channels.push(Channel {
+                name: name.clone(),
+                greetings: self.greetings.clone(),
+                watch_list: Vec::new(),
+                logging: true,
+                auto_op: true,
+            });
+        }
+
+        channels.extend(self.channels.iter().cloned());
+        channels
+    }
+
+    pub fn home_channel(&self) -> &str {
+        match (self.channel.as_ref(), self.channels.first()) {
+            (Some(channel), _) => channel,
+            (None, Some(channel)) => &channel.name,
+
+            // read_config won't hand out a Server without a channel.
+            (None, None) => panic!("no channels configured"),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Channel {
+    pub name: String,
+    #[serde(default)]
     pub greetings: Vec<Greeting>,
+    #[serde(default)]
+    pub watch_list: Vec<Mask>,
+    #[serde(default = "enabled")]
+    pub logging: bool,
+    #[serde(default = "enabled")]
+    pub auto_op: bool,
+}
+
+fn enabled() -> bool {
+    true
 }
 
 #[derive(Deserialize)]
@@ -27,6 +125,301 @@ pub struct Twilio {
     pub recipient: String,
 }
 
+/// An SMTP relay to send notifications through. `server` is `host:port`; STARTTLS needs the
+/// `ssl` feature.
+#[derive(Clone, Deserialize)]
+pub struct Email {
+    pub server: String,
+    #[serde(default)]
+    pub starttls: bool,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub recipient: String,
+}
+
+/// An HTTP endpoint to POST notifications to as JSON. With a `secret`, each request carries an
+/// `X-Watcher-Signature: sha256=<hmac>` header computed over the body.
+#[derive(Clone, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Notifications {
+    /// Where every notification attempt is recorded, so `.messages` has something to report.
+    /// See `history_path`.
+    pub history: Option<String>,
+    /// Seconds to hold on to notifications withheld for being too frequent before sending them
+    /// as a digest. Throttled ones go out as soon as there's room.
+    #[serde(default = "default_digest_interval")]
+    pub digest_interval: u64,
+    /// Which comings and goings of watched nicks are worth a notification: `join` and `part`.
+    #[serde(default = "default_watch_events")]
+    pub watch_events: Vec<String>,
+    /// Notifies about everyone's comings and goings, not just those on a watch list. Recipients
+    /// and per-channel watch lists only matter when this is off.
+    #[serde(default = "enabled")]
+    pub watch_everyone: bool,
+    /// How many times a send that failed outright is tried again before it's given up on, and
+    /// how many seconds to wait before the first retry. The wait doubles each time.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    #[serde(default = "default_retry_delay")]
+    pub retry_delay: u64,
+    #[serde(default)]
+    pub limits: Vec<Limit>,
+    #[serde(default)]
+    pub quiet_hours: Vec<QuietHours>,
+    #[serde(default)]
+    pub highlights: Highlights,
+    /// How each kind of event reads, by event name, with `{nick}`, `{channel}`, `{message}`,
+    /// `{time}` and `{network}` filled in.
+    #[serde(default)]
+    pub templates: HashMap<String, String>,
+    /// Leaves what was said in PMs out of text messages.
+    #[serde(default)]
+    pub private: bool,
+    #[serde(default)]
+    pub routes: Vec<Route>,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Notifications {
+            history: None,
+            digest_interval: default_digest_interval(),
+            watch_events: default_watch_events(),
+            watch_everyone: true,
+            retries: default_retries(),
+            retry_delay: default_retry_delay(),
+            limits: Vec::new(),
+            quiet_hours: Vec::new(),
+            highlights: Highlights::default(),
+            templates: HashMap::new(),
+            private: false,
+            routes: Vec::new(),
+        }
+    }
+}
+
+impl Notifications {
+    /// `history` if it's set, or else `notifications.log` beside the chat logs.
+    pub fn history_path(&self, logging: Option<&Logging>) -> String {
+        match (self.history.as_ref(), logging) {
+            (Some(history), _) => history.clone(),
+            (None, Some(logging)) => format!("{}/notifications.log", logging.path),
+            (None, None) => String::from("notifications.log"),
+        }
+    }
+}
+
+fn default_digest_interval() -> u64 {
+    15 * 60
+}
+
+fn default_watch_events() -> Vec<String> {
+    vec![String::from("join")]
+}
+
+fn default_retries() -> u32 {
+    5
+}
+
+fn default_retry_delay() -> u64 {
+    30
+}
+
+/// At most `count` notifications every `period` seconds, counted across all sinks or separately
+/// for each sink or recipient.
+#[derive(Clone, Deserialize)]
+pub struct Limit {
+    pub count: usize,
+    pub period: u64,
+    #[serde(default)]
+    pub per: LimitScope,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitScope {
+    All,
+    Sink,
+    Recipient,
+}
+
+impl Default for LimitScope {
+    fn default() -> Self {
+        LimitScope::All
+    }
+}
+
+/// Channel messages matching any of these regexes, or mentioning the owner's nick if `owner`
+/// is set, become notifications along with the `context` lines said before them.
+#[derive(Clone, Deserialize)]
+pub struct Highlights {
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    #[serde(default = "enabled")]
+    pub owner: bool,
+    #[serde(default = "default_context")]
+    pub context: usize,
+}
+
+impl Default for Highlights {
+    fn default() -> Self {
+        Highlights {
+            patterns: Vec::new(),
+            owner: true,
+            context: default_context(),
+        }
+    }
+}
+
+fn default_context() -> usize {
+    3
+}
+
+/// No notifications between `start` and `end` ("23:00", "07:00") in the recipient's time,
+/// given as an IANA `time_zone` ("America/New_York"). `days` are the days quiet hours begin on, every day if
+/// empty. Empty `sinks` and a missing `recipient` match any.
+#[derive(Clone, Deserialize)]
+pub struct QuietHours {
+    #[serde(default)]
+    pub sinks: Vec<String>,
+    pub recipient: Option<String>,
+    pub start: String,
+    pub end: String,
+    #[serde(default)]
+    pub days: Vec<String>,
+    #[serde(default = "default_time_zone")]
+    pub time_zone: String,
+    /// Whether to hold what comes in for a summary once quiet hours end, rather than drop it.
+    #[serde(default = "enabled")]
+    pub hold: bool,
+    /// Anything containing one of these words gets through anyway.
+    #[serde(default)]
+    pub urgent: Vec<String>,
+}
+
+impl QuietHours {
+    /// The start time, in minutes after midnight.
+    pub fn start(&self) -> Option<u32> {
+        parse_clock(&self.start)
+    }
+
+    /// The end time, in minutes after midnight.
+    pub fn end(&self) -> Option<u32> {
+        parse_clock(&self.end)
+    }
+
+    pub fn zone(&self) -> Option<Tz> {
+        self.time_zone.parse().ok()
+    }
+
+    pub fn days(&self) -> Option<Vec<Weekday>> {
+        self.days.iter().map(|day| parse_weekday(day)).collect()
+    }
+}
+
+fn default_time_zone() -> String {
+    String::from("UTC")
+}
+
+/// "HH:MM" as minutes after midnight.
+fn parse_clock(s: &str) -> Option<u32> {
+    let mut parts = s.splitn(2, ':');
+    let hours = parts.next().and_then(|hours| hours.parse::<u32>().ok());
+    let minutes = parts.next().and_then(|minutes| minutes.parse::<u32>().ok());
+
+    match (hours, minutes) {
+        (Some(hours), Some(minutes)) if hours < 24 && minutes < 60 => Some(hours * 60 + minutes),
+        _ => None,
+    }
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    match &*s.to_lowercase() {
+        "mon" | "monday" => Some(Weekday::Mon),
+        "tue" | "tuesday" => Some(Weekday::Tue),
+        "wed" | "wednesday" => Some(Weekday::Wed),
+        "thu" | "thursday" => Some(Weekday::Thu),
+        "fri" | "friday" => Some(Weekday::Fri),
+        "sat" | "saturday" => Some(Weekday::Sat),
+        "sun" | "sunday" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// Sends notifications matching `events` (e.g. "pm", "join") and `nicks` to the named `sinks`
+/// ("sms", "email", "webhook"). A route with just the one sink can send to someone other than
+/// that sink's usual recipient.
+#[derive(Clone, Deserialize)]
+pub struct Route {
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub nicks: Vec<Mask>,
+    pub sinks: Vec<String>,
+    pub recipient: Option<String>,
+}
+
+/// Someone else who wants notifications from the same bot: how to reach them, and which nicks
+/// and events they care about. Empty lists mean anyone and anything.
+#[derive(Clone, Deserialize)]
+pub struct Recipient {
+    pub sink: String,
+    pub contact: String,
+    #[serde(default)]
+    pub watch_list: Vec<Mask>,
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Seconds between notifications about the same nick; `bot.message_frequency` if left out.
+    pub frequency: Option<u64>,
+}
+
+/// Whose notifications these are. While they're visibly around on IRC--in one of our
+/// channels, not away, and having spoken in the last `active_for` seconds--nothing is sent.
+#[derive(Clone, Deserialize)]
+pub struct Owner {
+    pub nick: Mask,
+    #[serde(default = "default_active_for")]
+    pub active_for: u64,
+}
+
+fn default_active_for() -> u64 {
+    10 * 60
+}
+
+/// Where to listen for Twilio's inbound SMS webhook, so the owner can text replies back into
+/// IRC. `url` is the webhook URL as Twilio has it, which it signs every request with; the
+/// listener answers on its path. Only signed messages from the Twilio `recipient` number are
+/// relayed.
+#[derive(Clone, Deserialize)]
+pub struct Inbound {
+    pub address: String,
+    pub url: String,
+}
+
+impl Inbound {
+    /// The path part of `url`, or `None` if it isn't an http or https URL.
+    pub fn path(&self) -> Option<&str> {
+        let rest = if self.url.starts_with("https://") {
+            &self.url["https://".len()..]
+        } else if self.url.starts_with("http://") {
+            &self.url["http://".len()..]
+        } else {
+            return None;
+        };
+
+        let rest = rest.split(|c| c == '?' || c == '#').next().unwrap_or("");
+        Some(rest.find('/').map_or("/", |idx| &rest[idx..]))
+    }
+}
+
 #[derive(Clone, Deserialize)]
 pub struct User {
     pub nick: String,
@@ -43,8 +436,15 @@ pub struct Config {
     pub bot: Bot,
     pub server: Server,
     pub user: User,
-    pub twilio: Twilio,
+    pub owner: Option<Owner>,
+    pub twilio: Option<Twilio>,
+    pub email: Option<Email>,
+    pub webhook: Option<Webhook>,
+    pub inbound: Option<Inbound>,
+    pub recipients: Vec<Recipient>,
+    pub notifications: Notifications,
     pub logging: Option<Logging>,
+    pub state: State,
 }
 
 #[derive(Debug)]
@@ -84,17 +484,167 @@ pub fn read_config(path: &str) -> Result<Config, ConfigError> {
                 }
             };
 
+            let bot: Bot = decode_section("bot", table.get("bot").cloned())?;
+            if let Some(ref auth) = bot.auth {
+                validate_auth(auth)?;
+            }
+
+            let state = State::load(&bot.state)
+                .map_err(|e| ConfigError::BadElement(format!("state file {}: {}", bot.state, e)))?;
+
+            // eirsee only ever tells us a sender's nick, so anything with a user or host part
+            // would never match, and a nick is all an admin could be checked against anyway.
+            nick_patterns("admin and trusted entries", bot.admin.iter().chain(&bot.trusted).chain(&state.admin_added))?;
+
+            let server: Server = decode_section("server", table.get("server").cloned())?;
+            if server.channel.is_none() && server.channels.is_empty() {
+                return Err(ConfigError::MissingElement(String::from("server.channels")));
+            }
+
+            let twilio: Option<Twilio> = decode_optional_section("twilio", table.get("twilio").cloned())?;
+            let email: Option<Email> = decode_optional_section("email", table.get("email").cloned())?;
+            let webhook: Option<Webhook> = decode_optional_section("webhook", table.get("webhook").cloned())?;
+            if let Some(ref email) = email {
+                // AUTH PLAIN is the password in base64; it doesn't go over a connection in the clear.
+                if (email.username.is_some() || email.password.is_some()) && !email.starttls {
+                    return Err(ConfigError::BadElement(String::from("email.username and email.password need email.starttls = true")));
+                }
+            }
+            let inbound: Option<Inbound> = decode_optional_section("inbound", table.get("inbound").cloned())?;
+            if inbound.is_some() && twilio.is_none() {
+                return Err(ConfigError::BadElement(String::from("inbound SMS needs a [twilio] recipient to accept replies from")));
+            }
+            if let Some(ref inbound) = inbound {
+                if inbound.path().is_none() {
+                    return Err(ConfigError::BadElement(format!("inbound.url {} isn't an http or https URL", inbound.url)));
+                }
+            }
+            let notifications: Notifications = decode_optional_section("notifications", table.get("notifications").cloned())?
+                .unwrap_or_default();
+
+            if notifications.limits.iter().any(|limit| limit.count == 0 || limit.period == 0) {
+                return Err(ConfigError::BadElement(String::from("notification limits need a count and period of at least 1")));
+            }
+
+            for event in &notifications.watch_events {
+                match &**event {
+                    "join" | "part" => (),
+                    _ => return Err(ConfigError::BadElement(format!("unknown watch event: {}", event))),
+                }
+            }
+
+            for kind in notifications.templates.keys() {
+                if !["join", "pm", "mention", "part", "health", "digest"].contains(&&**kind) {
+                    return Err(ConfigError::BadElement(format!("template for unknown event: {}", kind)));
+                }
+            }
+
+            for pattern in &notifications.highlights.patterns {
+                if let Err(e) = Regex::new(pattern) {
+                    return Err(ConfigError::BadElement(format!("highlight pattern {}: {}", pattern, e)));
+                }
+            }
+
+            for quiet in &notifications.quiet_hours {
+                if quiet.start().is_none() || quiet.end().is_none() || quiet.zone().is_none() || quiet.days().is_none() {
+                    return Err(ConfigError::BadElement(format!(
+                        "quiet hours {}-{} need HH:MM times, a time_zone like America/New_York and day names",
+                        quiet.start,
+                        quiet.end
+                    )));
+                }
+            }
+
+            let recipients: Vec<Recipient> = decode_optional_section("recipients", table.get("recipients").cloned())?
+                .unwrap_or_default();
+            let owner: Option<Owner> = decode_optional_section("owner", table.get("owner").cloned())?;
+
+            // The same goes for everyone being watched.
+            nick_patterns("watch list entries", bot.watch_list.iter().chain(server.channels.iter().flat_map(|channel| &channel.watch_list)))?;
+            nick_patterns("route nicks", notifications.routes.iter().flat_map(|route| &route.nicks))?;
+            nick_patterns("recipient watch list entries", recipients.iter().flat_map(|recipient| &recipient.watch_list))?;
+            nick_patterns("the owner's nick", owner.iter().map(|owner| &owner.nick))?;
+
+            // Scoped so the sinks can be moved into the config afterwards.
+            {
+                let configured = |sink: &str| match sink {
+                    "sms" => twilio.is_some(),
+                    "email" => email.is_some(),
+                    "webhook" => webhook.is_some(),
+                    _ => false,
+                };
+
+                for route in &notifications.routes {
+                    // A number is no use to the email sink, so an override is only for one sink.
+                    if route.recipient.is_some() && route.sinks.len() != 1 {
+                        return Err(ConfigError::BadElement(format!(
+                            "notification route with a recipient must name exactly one sink, not {:?}",
+                            route.sinks
+                        )));
+                    }
+
+                    for sink in &route.sinks {
+                        if !configured(sink) {
+                            return Err(ConfigError::BadElement(format!("notification route names unconfigured sink: {}", sink)));
+                        }
+                    }
+                }
+
+                for recipient in &recipients {
+                    if !configured(&recipient.sink) {
+                        return Err(ConfigError::BadElement(format!("recipient {} names unconfigured sink: {}", recipient.contact, recipient.sink)));
+                    }
+
+                    for event in &recipient.events {
+                        match &**event {
+                            "join" | "pm" | "mention" | "part" | "health" | "digest" => (),
+                            _ => return Err(ConfigError::BadElement(format!("recipient {} watches for unknown event: {}", recipient.contact, event))),
+                        }
+                    }
+                }
+            }
+
             Ok(Config {
-                bot: decode_section("bot", table.get("bot").cloned())?,
-                server: decode_section("server", table.get("server").cloned())?,
+                bot: bot,
+                server: server,
                 user: decode_section("user", table.get("user").cloned())?,
-                twilio: decode_section("twilio", table.get("twilio").cloned())?,
+                owner: owner,
+                twilio: twilio,
+                email: email,
+                webhook: webhook,
+                inbound: inbound,
+                recipients: recipients,
+                notifications: notifications,
                 logging: logging,
+                state: state,
             })
         }
     }
 }
 
+fn nick_patterns<'a, I: IntoIterator<Item = &'a Mask>>(what: &str, masks: I) -> Result<(), ConfigError> {
+    match masks.into_iter().find(|mask| !mask.is_nick_pattern()) {
+        Some(mask) => Err(ConfigError::BadElement(format!("{} must be nick patterns: {}", what, mask))),
+        None => Ok(()),
+    }
+}
+
+/// The longest a password session can go unseen. Sessions belong to a nick, and whoever takes
+/// the nick next could use what's left of one.
+const MAX_SESSION_TIMEOUT: u64 = 10 * 60;
+
+fn validate_auth(auth: &Auth) -> Result<(), ConfigError> {
+    match (auth.mode, &auth.salt, &auth.hash) {
+        (AuthMode::Password, &Some(_), &Some(_)) if auth.session_timeout > MAX_SESSION_TIMEOUT => Err(ConfigError::BadElement(format!(
+            "bot.auth.session_timeout can be at most {} seconds",
+            MAX_SESSION_TIMEOUT
+        ))),
+        (AuthMode::Password, &Some(_), &Some(_)) => Ok(()),
+        (AuthMode::Password, _, _) => Err(ConfigError::MissingElement(String::from("bot.auth.salt/hash"))),
+        _ => Ok(()),
+    }
+}
+
 fn validate_logging(logging: &Logging) -> Result<(), String> {
     match fs::create_dir_all(&logging.path) {
         Ok(_) => Ok(()),
@@ -102,6 +652,14 @@ fn validate_logging(logging: &Logging) -> Result<(), String> {
     }
 }
 
+fn decode_optional_section<'d, T: Deserialize<'d>>(name: &str, value: Option<Value>) -> Result<Option<T>, ConfigError> {
+    match decode_section(name, value) {
+        Err(ConfigError::MissingElement(_)) => Ok(None),
+        Err(e) => Err(e),
+        Ok(section) => Ok(Some(section)),
+    }
+}
+
 fn decode_section<'d, T: Deserialize<'d>>(name: &str, value: Option<Value>) -> Result<T, ConfigError> {
     match value {
         None => Err(ConfigError::MissingElement(name.to_string())),
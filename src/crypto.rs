@@ -0,0 +1,113 @@
+use sha1::Sha1;
+use sha2::Sha256;
+
+/// Compares the whole of both, so how long it takes says nothing about where they differ.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// What Twilio signs its requests with.
+pub fn hmac_sha1(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac(key, data, |parts| {
+        use sha1::Digest;
+
+        let mut hasher = Sha1::default();
+        for part in parts {
+            hasher.input(part);
+        }
+        hasher.result().to_vec()
+    })
+}
+
+/// What webhook requests are signed with.
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac(key, data, sha256)
+}
+
+/// SHA-256 of everything in `parts`, one after the other.
+pub fn sha256(parts: &[&[u8]]) -> Vec<u8> {
+    use sha2::Digest;
+
+    let mut hasher = Sha256::default();
+    for part in parts {
+        hasher.input(part);
+    }
+    hasher.result().to_vec()
+}
+
+/// RFC 2104, over whichever hash; sha-1 and sha2 don't necessarily share a `Digest` trait to be
+/// generic over. Both hashes here have 64-byte blocks.
+fn hmac<H: Fn(&[&[u8]]) -> Vec<u8>>(key: &[u8], data: &[u8], hash: H) -> Vec<u8> {
+    const BLOCK_SIZE: usize = 64;
+
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        let hashed = hash(&[key]);
+        block[..hashed.len()].copy_from_slice(&hashed);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let inner_pad: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    let outer_pad: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    let inner = hash(&[&inner_pad[..], data]);
+    hash(&[&outer_pad[..], &inner[..]])
+}
+
+/// Lowercase hex, two digits a byte.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Standard base64, padded.
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * idx)) & 0x3f] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, constant_time_eq, hex, hmac_sha1, hmac_sha256};
+
+    #[test]
+    fn hmacs_match_the_rfcs() {
+        // RFC 2202 and RFC 4231, test case 2 of each.
+        assert_eq!("effcdf6ae5eb2fa2d27416d5f184df9c259a7c79", hex(&hmac_sha1(b"Jefe", b"what do ya want for nothing?")));
+        assert_eq!(
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?"))
+        );
+    }
+
+    #[test]
+    fn base64_pads_correctly() {
+        assert_eq!("", base64(b""));
+        assert_eq!("Zg==", base64(b"f"));
+        assert_eq!("Zm8=", base64(b"fo"));
+        assert_eq!("Zm9v", base64(b"foo"));
+        assert_eq!("Zm9vYg==", base64(b"foob"));
+        assert_eq!("AHVzZXIAcGFzcw==", base64(b"\0user\0pass"));
+    }
+
+    #[test]
+    fn comparisons_go_by_the_whole_value() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secrex"));
+        assert!(!constant_time_eq(b"secret", b"secret!"));
+    }
+}
@@ -1,5 +1,6 @@
-use regex::Regex;
-use serde::{Deserialize, Deserializer};
+use regex::{self, Regex};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 use std::slice;
 
 #[derive(Clone)]
@@ -10,6 +11,19 @@ pub struct Greeting {
 }
 
 impl Greeting {
+    pub fn new(passthru: bool, filter: Option<&str>, message: String) -> Result<Greeting, regex::Error> {
+        let filter = match filter {
+            None => None,
+            Some(filter) => Some(Regex::new(filter)?),
+        };
+
+        Ok(Greeting {
+            passthru: passthru,
+            filter: filter,
+            message: message,
+        })
+    }
+
     #[inline]
     pub fn message(&self, nick: &str) -> String {
         self.message.replace("{nick}", nick)
@@ -21,6 +35,20 @@ impl Greeting {
     }
 }
 
+impl fmt::Display for Greeting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref filter) = self.filter {
+            write!(f, "filter={} ", filter.as_str())?;
+        }
+
+        if self.passthru {
+            f.write_str("passthru ")?;
+        }
+
+        f.write_str(&self.message)
+    }
+}
+
 pub struct GreetingsForUser<'a>
 {
     user: &'a str,
@@ -88,6 +116,18 @@ impl<'d> Deserialize<'d> for Greeting {
     }
 }
 
+impl Serialize for Greeting {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut greeting = s.serialize_struct("Greeting", 3)?;
+        greeting.serialize_field("passthru", &self.passthru)?;
+        greeting.serialize_field("filter", &self.filter.as_ref().map(|filter| filter.as_str()))?;
+        greeting.serialize_field("message", &self.message)?;
+        greeting.end()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Greeting, Greetings};
@@ -98,6 +138,17 @@ mod tests {
         assert!(greeting().is_valid("John"));
     }
 
+    #[test]
+    fn invalid_filters_are_rejected() {
+        assert!(Greeting::new(false, Some("(unclosed"), "Hi!".to_owned()).is_err());
+    }
+
+    #[test]
+    fn display_round_trips_the_greet_syntax() {
+        let greeting = Greeting::new(true, Some("^bob"), "Hi, {nick}!".to_owned()).unwrap();
+        assert_eq!("filter=^bob passthru Hi, {nick}!", greeting.to_string());
+    }
+
     #[test]
     fn message_value() {
         assert_eq!("Hello, John!", greeting().message("John"));
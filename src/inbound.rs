@@ -0,0 +1,317 @@
+use config;
+use crypto::{base64, constant_time_eq, hmac_sha1};
+use eirsee::message::OutgoingMessage;
+use hyper;
+use hyper::method::Method;
+use hyper::server::{Handler, Listening, Request, Response, Server};
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+use std::io::Read;
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
+
+const USAGE: &'static str = "Start with @nick or #channel, then your message.";
+
+/// A text is at most 1600 characters, so Twilio's form never comes anywhere near this.
+const MAX_BODY: u64 = 64 * 1024;
+
+/// A text from the owner, on its way into IRC.
+#[derive(Debug, PartialEq)]
+pub enum Relay {
+    Private(String, String),
+    Channel(String, String),
+}
+
+impl Relay {
+    /// `@alice thanks, back in 10` goes to alice; `#hello on my way` goes to #hello.
+    pub fn parse(body: &str) -> Option<Relay> {
+        // A text can have line breaks in it, and we don't want any of them turning into a second
+        // IRC command.
+        let body = body.split(|c| c == '\r' || c == '\n')
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut parts = body.splitn(2, ' ');
+        let target = parts.next().unwrap_or("").trim_right_matches(|c: char| c == ':' || c == ',');
+        let text = parts.next().map_or("", |text| text.trim());
+
+        if target.len() < 2 || text.is_empty() {
+            return None;
+        }
+
+        if target.starts_with('@') {
+            Some(Relay::Private(target[1..].to_owned(), text.to_owned()))
+        } else if target.starts_with('#') || target.starts_with('&') {
+            Some(Relay::Channel(target.to_lowercase(), text.to_owned()))
+        } else {
+            None
+        }
+    }
+
+    /// eirsee only knows about its home channel, so channel messages go out raw.
+    fn into_message(self) -> OutgoingMessage {
+        match self {
+            Relay::Private(nick, text) => OutgoingMessage::to_private(nick, text),
+            Relay::Channel(channel, text) => OutgoingMessage::Raw(format!("PRIVMSG {} :{}", channel, text)),
+        }
+    }
+}
+
+/// Answers Twilio's inbound SMS webhook, passing anything the owner texts us on to IRC.
+struct Listener {
+    url: String,
+    path: String,
+    from: String,
+    token: String,
+    irc: Mutex<Sender<OutgoingMessage>>,
+}
+
+impl Listener {
+    fn respond(&self, req: &mut Request) -> (StatusCode, Option<&'static str>) {
+        let path = match req.uri {
+            RequestUri::AbsolutePath(ref path) => path.split('?').next().unwrap_or("").to_owned(),
+            _ => return (StatusCode::NotFound, None),
+        };
+
+        if path != self.path {
+            return (StatusCode::NotFound, None);
+        }
+
+        if req.method != Method::Post {
+            return (StatusCode::MethodNotAllowed, None);
+        }
+
+        let signature = match req.headers.get_raw("X-Twilio-Signature") {
+            Some(values) if values.len() == 1 => String::from_utf8_lossy(&values[0]).into_owned(),
+            _ => return (StatusCode::Forbidden, None),
+        };
+
+        let mut body = String::new();
+        match req.by_ref().take(MAX_BODY + 1).read_to_string(&mut body) {
+            Err(_) => return (StatusCode::BadRequest, None),
+            Ok(read) if read as u64 > MAX_BODY => return (StatusCode::PayloadTooLarge, None),
+            Ok(_) => (),
+        }
+
+        let form = parse_form(&body);
+        let field = |name: &str| form.iter().find(|&&(ref key, _)| key == name).map_or("", |&(_, ref value)| &**value);
+
+        // Anyone who finds the URL can post to it, so only Twilio's word counts.
+        if !constant_time_eq(signature.as_bytes(), sign(&self.token, &self.url, &form).as_bytes()) {
+            println!("ignoring unsigned inbound SMS");
+            return (StatusCode::Forbidden, None);
+        }
+
+        if digits(field("From")) != digits(&self.from) {
+            println!("ignoring inbound SMS from {}", field("From"));
+            return (StatusCode::Forbidden, None);
+        }
+
+        let relay = match Relay::parse(field("Body")) {
+            None => return (StatusCode::Ok, Some(USAGE)),
+            Some(relay) => relay,
+        };
+
+        let sent = match self.irc.lock() {
+            Ok(irc) => irc.send(relay.into_message()).is_ok(),
+            Err(_) => false,
+        };
+
+        if sent {
+            (StatusCode::Ok, None)
+        } else {
+            (StatusCode::ServiceUnavailable, None)
+        }
+    }
+}
+
+impl Handler for Listener {
+    fn handle(&self, mut req: Request, mut res: Response) {
+        let (status, reply) = self.respond(&mut req);
+        *res.status_mut() = status;
+
+        if status != StatusCode::Ok {
+            res.send(b"").ok();
+            return;
+        }
+
+        // Twilio texts back whatever is in the TwiML <Message>, if anything.
+        let twiml = match reply {
+            None => String::from("<Response></Response>"),
+            Some(reply) => format!("<Response><Message>{}</Message></Response>", reply),
+        };
+
+        res.headers_mut().set_raw("Content-Type", vec![b"text/xml".to_vec()]);
+        res.send(twiml.as_bytes()).ok();
+    }
+}
+
+/// Starts listening in the background. Dropping the result waits on the listener forever, so
+/// hang on to it for as long as the bot runs.
+pub fn listen(config: &config::Inbound, twilio: &config::Twilio, irc: Sender<OutgoingMessage>) -> hyper::Result<Listening> {
+    let listener = Listener {
+        url: config.url.clone(),
+        path: config.path().unwrap_or("/").to_owned(),
+        from: twilio.recipient.clone(),
+        token: twilio.token.clone(),
+        irc: Mutex::new(irc),
+    };
+
+    Server::http(&*config.address)?.handle(listener)
+}
+
+/// What Twilio puts in `X-Twilio-Signature`: the URL it posted to, followed by each form field's
+/// name and value sorted by name, signed with the account's auth token and base64 encoded.
+fn sign(token: &str, url: &str, form: &[(String, String)]) -> String {
+    let mut fields: Vec<_> = form.iter().collect();
+    fields.sort();
+
+    let mut data = url.to_owned();
+    for &&(ref key, ref value) in &fields {
+        data.push_str(key);
+        data.push_str(value);
+    }
+
+    base64(&hmac_sha1(token.as_bytes(), data.as_bytes()))
+}
+
+/// Just the digits of a phone number, so `+1 (555) 123-4567` and `+15551234567` compare equal.
+fn digits(number: &str) -> String {
+    number.chars().filter(|c| c.is_digit(10)).collect()
+}
+
+/// Decodes `application/x-www-form-urlencoded` data, which is how Twilio posts.
+fn parse_form(body: &str) -> Vec<(String, String)> {
+    body.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("");
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'+' => decoded.push(b' '),
+            b'%' if idx + 2 < bytes.len() => {
+                match (hex(bytes[idx + 1]), hex(bytes[idx + 2])) {
+                    (Some(high), Some(low)) => {
+                        decoded.push(high * 16 + low);
+                        idx += 2;
+                    }
+                    _ => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        idx += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{Inbound, Twilio};
+    use eirsee::message::OutgoingMessage;
+    use hyper::Client;
+    use hyper::header::{ContentType, Headers};
+    use hyper::status::StatusCode;
+    use std::io::Read;
+    use std::iter;
+    use std::sync::mpsc;
+    use super::{decode, listen, parse_form, sign, Relay, MAX_BODY};
+
+    #[test]
+    fn replies_name_where_they_go() {
+        assert_eq!(
+            Some(Relay::Private("alice".to_owned(), "thanks, back in 10".to_owned())),
+            Relay::parse("@alice thanks, back in 10")
+        );
+        assert_eq!(Some(Relay::Channel("#hello".to_owned(), "on my way".to_owned())), Relay::parse("#Hello: on my way"));
+        assert_eq!(
+            Some(Relay::Channel("#hello".to_owned(), "one QUIT :two".to_owned())),
+            Relay::parse("#hello one\r\nQUIT :two")
+        );
+        assert_eq!(None, Relay::parse("thanks"));
+        assert_eq!(None, Relay::parse("@alice"));
+    }
+
+    #[test]
+    fn form_values_are_decoded() {
+        assert_eq!("@alice thanks, 100%", decode("%40alice+thanks%2C+100%25"));
+        assert_eq!("50% off", decode("50%+off"));
+    }
+
+    #[test]
+    fn signatures_are_twilios() {
+        // The fields are signed in order of name, whatever order they came in.
+        let form = parse_form("To=%2B15550000000&From=%2B15551234567");
+        let mut sorted = form.clone();
+        sorted.reverse();
+        assert_eq!(sign("token", "https://example.org/sms", &form), sign("token", "https://example.org/sms", &sorted));
+        assert!(sign("token", "https://example.org/sms", &form) != sign("other", "https://example.org/sms", &form));
+    }
+
+    #[test]
+    fn texts_from_the_recipient_are_relayed() {
+        let (irc, messages) = mpsc::channel();
+        let twilio = Twilio {
+            sid: String::from("sid"),
+            token: String::from("token"),
+            number: String::from("+15550000000"),
+            recipient: String::from("+15551234567"),
+        };
+
+        // Twilio signs the URL it was given, whatever the listener happens to be bound to.
+        let url = "https://example.org/sms";
+        let config = Inbound { address: String::from("127.0.0.1:0"), url: url.to_owned() };
+        let mut listening = listen(&config, &twilio, irc).unwrap();
+        let local = format!("http://{}/sms", listening.socket);
+
+        let post_as = |token: Option<&str>, body: &str| {
+            let mut headers = Headers::new();
+            headers.set(ContentType::form_url_encoded());
+            if let Some(token) = token {
+                headers.set_raw("X-Twilio-Signature", vec![sign(token, url, &parse_form(body)).into_bytes()]);
+            }
+            Client::new().post(&*local).headers(headers).body(body).send().unwrap()
+        };
+        let post = |body: &str| post_as(Some("token"), body);
+
+        let mut response = post("From=%2B15551234567&To=%2B15550000000&Body=%23hello+on+my+way");
+        let mut twiml = String::new();
+        response.read_to_string(&mut twiml).unwrap();
+        assert_eq!(StatusCode::Ok, response.status);
+        assert_eq!("<Response></Response>", twiml);
+
+        match messages.try_recv() {
+            Ok(OutgoingMessage::Raw(line)) => assert_eq!("PRIVMSG #hello :on my way", line),
+            _ => panic!("nothing relayed"),
+        }
+
+        assert_eq!(StatusCode::Forbidden, post("From=%2B15559999999&Body=%23hello+hi").status);
+        assert_eq!(StatusCode::Forbidden, post_as(None, "From=%2B15551234567&Body=%23hello+hi").status);
+        assert_eq!(StatusCode::Forbidden, post_as(Some("guess"), "From=%2B15551234567&Body=%23hello+hi").status);
+
+        let huge = format!("From=%2B15551234567&Body={}", iter::repeat('a').take(MAX_BODY as usize).collect::<String>());
+        assert_eq!(StatusCode::PayloadTooLarge, post(&huge).status);
+        assert!(messages.try_recv().is_err());
+
+        listening.close().unwrap();
+    }
+}
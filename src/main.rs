@@ -4,64 +4,145 @@
 extern crate serde_derive;
 
 extern crate chrono;
+extern crate chrono_tz;
 extern crate dice;
 extern crate eirsee;
 extern crate fortune_cookie;
+extern crate hyper;
+#[cfg(feature = "ssl")]
+extern crate hyper_native_tls;
 extern crate icndb;
+#[cfg(feature = "ssl")]
+extern crate native_tls;
 extern crate quote_rs;
 extern crate rand;
 extern crate regex;
 extern crate rsilio;
 extern crate serde;
+extern crate serde_json;
+extern crate sha1;
+extern crate sha2;
 extern crate toml;
 
+mod auth;
 mod command;
 mod config;
+mod crypto;
 mod greetings;
+mod inbound;
+mod mask;
 mod notifications;
+mod state;
 mod watcher;
 
 use config::Config;
 use eirsee::message::OutgoingMessage;
-use std::sync::mpsc;
+use notifications::NotificationService;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
 use watcher::Watcher;
 
-fn main() {
-    use std::io::BufRead;
+/// How long the delivery worker gets to finish up when the bot shuts down.
+const DRAIN_SECS: u64 = 10;
 
+fn main() {
     match config::read_config(&std::env::args().nth(1).unwrap_or_else(|| String::from("bot.toml"))) {
         Err(e) => panic!("{:?}", e),
         Ok(ref config) => {
-            let handle = run_bot(config);
-            let stdin = std::io::stdin();
-
-            for mut line in stdin.lock().lines().filter_map(|s| s.ok()) {
-                match line.pop() {
-
-                    // To be clear, what happens here is that just forwarding messages from console
-                    // input. Lines beginning with `#` are sent as channel messages, while lines
-                    // beginning with `r` are sent as raw IRC messages. Note: if you add a space
-                    // after the format specifier `(#|r)`, that space will be included in the
-                    // message as sent.
-                    Some('#') => handle.send(OutgoingMessage::ChannelMessage { content: line }).unwrap(),
-                    Some('r') => handle.send(OutgoingMessage::Raw(line)).unwrap(),
+            let (shutdown, shutdown_requested) = mpsc::channel();
+            let watcher = Watcher::with_config(config, shutdown.clone());
+            let notifications = watcher.notifications();
+            let handle = run_bot(config, watcher);
+            run_console(handle.clone(), shutdown, config.logging.as_ref().map(|logging| logging.path.clone()));
+            let _inbound = run_inbound(config, handle.clone());
 
-                    _ => (), // wtf who cares.
-                }
-            }
+            // Either `.kill` or the console will tell us when it's time to go.
+            let reason = shutdown_requested.recv().unwrap_or_else(|_| String::from("Shutting down"));
+            quit(&handle, &notifications, &reason);
         }
     }
 }
 
-fn run_bot(config: &Config) -> mpsc::Sender<OutgoingMessage> {
+fn run_bot(config: &Config, watcher: Watcher) -> mpsc::Sender<OutgoingMessage> {
     use eirsee::core::Core;
     use eirsee::config::Config;
 
     let core = Core::with_config(Config {
         user: config.user.nick.clone(),
         name: config.user.real.clone(),
-        channel: config.server.channel.clone(),
+        channel: config.server.home_channel().to_owned(),
+    });
+
+    core.connect(&config.server.address, watcher)
+}
+
+fn run_console(handle: mpsc::Sender<OutgoingMessage>, shutdown: mpsc::Sender<String>, log_path: Option<String>) {
+    thread::spawn(move || {
+        use std::io::BufRead;
+
+        let stdin = std::io::stdin();
+        for mut line in stdin.lock().lines().filter_map(|s| s.ok()) {
+            match line.pop() {
+
+                // To be clear, what happens here is that just forwarding messages from console
+                // input. Lines beginning with `#` are sent as channel messages, while lines
+                // beginning with `r` are sent as raw IRC messages, and a line beginning with `q`
+                // shuts the bot down, using the rest of the line as the quit message. Note: if you
+                // add a space after the format specifier `(#|r|q)`, that space will be included in
+                // the message as sent.
+                Some('#') => handle.send(OutgoingMessage::ChannelMessage { content: line }).unwrap(),
+                Some('r') => handle.send(OutgoingMessage::Raw(line)).unwrap(),
+                Some('q') => {
+                    // Whoever is at the console can do anything, but a shutdown still goes on record.
+                    watcher::audit(log_path.as_ref().map(|path| &**path), "console", None, &format!("{}q", line), true);
+                    shutdown.send(line).unwrap()
+                }
+
+                _ => (), // wtf who cares.
+            }
+        }
     });
+}
+
+/// Listens for the owner's texts, if there's anywhere to listen. The listener has to stay alive
+/// for as long as the bot does.
+fn run_inbound(config: &Config, handle: mpsc::Sender<OutgoingMessage>) -> Option<hyper::server::Listening> {
+    let (inbound, twilio) = match (config.inbound.as_ref(), config.twilio.as_ref()) {
+        (Some(inbound), Some(twilio)) => (inbound, twilio),
+        _ => return None,
+    };
+
+    match inbound::listen(inbound, twilio, handle) {
+        Ok(listening) => {
+            println!("listening for SMS replies on {}", listening.socket);
+            Some(listening)
+        }
+        Err(e) => {
+            println!("unable to listen for SMS replies on {}: {}", inbound.address, e);
+            None
+        }
+    }
+}
+
+/// Sees the last notifications out, says goodbye to the server and exits.
+///
+/// Logs and the notification history are written as they happen, but the delivery worker may
+/// still have sends queued or waiting on a retry. It gets a few seconds to finish them, and
+/// whatever it manages is recorded. Anything held for a digest, and the dead letters, are saved
+/// for next time. Then we give the connection a moment to deliver the QUIT before pulling the
+/// plug.
+fn quit(handle: &mpsc::Sender<OutgoingMessage>, notifications: &Mutex<NotificationService>, reason: &str) -> ! {
+    println!("shutting down: {}", reason);
+
+    // Going down anyway, so a panic elsewhere is no reason not to finish up.
+    let mut notifications = notifications.lock().unwrap_or_else(|e| e.into_inner());
+    let unfinished = notifications.shutdown(Duration::from_secs(DRAIN_SECS));
+    if unfinished > 0 {
+        println!("gave up waiting on {} notifications", unfinished);
+    }
 
-    core.connect(&config.server.address, Watcher::with_config(config))
+    handle.send(OutgoingMessage::Raw(format!("QUIT :{}", reason))).ok();
+    thread::sleep(Duration::from_secs(2));
+    std::process::exit(0)
 }
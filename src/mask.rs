@@ -0,0 +1,125 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// A `nick!user@host` glob pattern, as used in ban masks: `*` matches any run of characters and
+/// `?` matches any single character. Matching is case-insensitive.
+///
+/// A pattern without `!` or `@` is a plain nick pattern, which is matched against just the nick
+/// portion of a prefix. That keeps old config entries like `"boss"` working as before.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mask {
+    pattern: String,
+}
+
+impl Mask {
+    pub fn new<S: Into<String>>(pattern: S) -> Mask {
+        Mask { pattern: pattern.into().to_lowercase() }
+    }
+
+    pub fn matches(&self, prefix: &str) -> bool {
+        let prefix = prefix.to_lowercase();
+
+        if self.is_nick_pattern() {
+            glob(self.pattern.as_bytes(), nick(&prefix).as_bytes())
+        } else {
+            glob(self.pattern.as_bytes(), prefix.as_bytes())
+        }
+    }
+
+    #[inline]
+    pub fn is_nick_pattern(&self) -> bool {
+        !self.pattern.contains('!') && !self.pattern.contains('@')
+    }
+}
+
+impl fmt::Display for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+impl<'d> Deserialize<'d> for Mask {
+    fn deserialize<D: Deserializer<'d>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d).map(Mask::new)
+    }
+}
+
+impl Serialize for Mask {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.pattern)
+    }
+}
+
+/// True if any of the masks matches the prefix.
+#[inline]
+pub fn any_match(masks: &[Mask], prefix: &str) -> bool {
+    masks.iter().any(|mask| mask.matches(prefix))
+}
+
+/// The nick portion of a `nick!user@host` prefix.
+#[inline]
+pub fn nick(prefix: &str) -> &str {
+    prefix.split('!').next().unwrap_or(prefix)
+}
+
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    // Position of the last `*` seen, and the text position it is currently standing in for.
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(&b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+
+            _ => match backtrack {
+                None => return false,
+                Some((star, consumed)) => {
+                    backtrack = Some((star, consumed + 1));
+                    p = star + 1;
+                    t = consumed + 1;
+                }
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mask;
+
+    #[test]
+    fn nick_patterns_match_nick_only() {
+        let mask = Mask::new("Boss");
+
+        assert!(mask.matches("boss"));
+        assert!(mask.matches("boss!boss@example.org"));
+        assert!(!mask.matches("bossy!boss@example.org"));
+    }
+
+    #[test]
+    fn host_patterns_match_full_prefix() {
+        let mask = Mask::new("*!*@staff.example.org");
+
+        assert!(mask.matches("alice!alice@staff.example.org"));
+        assert!(mask.matches("Bob!~bob@STAFF.example.org"));
+        assert!(!mask.matches("alice!alice@example.org"));
+        assert!(!mask.matches("alice"));
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(Mask::new("al?ce*").matches("alice_away"));
+        assert!(Mask::new("*!*@*.example.org").matches("a!b@c.d.example.org"));
+        assert!(!Mask::new("a*c").matches("abcd"));
+    }
+}
@@ -0,0 +1,102 @@
+use notifications::{EventKind, NotificationEvent};
+
+/// Events that go together in a digest, along with everyone involved.
+struct Group<'a> {
+    first: &'a NotificationEvent,
+    nicks: Vec<&'a str>,
+    count: usize,
+}
+
+impl<'a> Group<'a> {
+    fn includes(&self, event: &NotificationEvent) -> bool {
+        let first = self.first;
+        first.kind == event.kind && first.channel == event.channel && match first.kind {
+            EventKind::Join | EventKind::Part => first.nick == event.nick,
+            EventKind::Health | EventKind::Digest => first.body == event.body,
+            EventKind::Pm | EventKind::Mention => true,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let channel = self.first.channel.as_ref().map_or("", |channel| &**channel);
+        let body = self.first.body.as_ref().map_or("", |body| &**body);
+
+        match self.first.kind {
+            EventKind::Pm => format!("{} from {}", plural(self.count, "PM"), self.nicks.join(", ")),
+            EventKind::Mention => format!("{} in {} from {}", plural(self.count, "mention"), channel, self.nicks.join(", ")),
+            EventKind::Join => format!("{} joined {}{}", self.first.nick, channel, times(self.count)),
+            EventKind::Part => format!("{} left {}{}", self.first.nick, channel, times(self.count)),
+            EventKind::Health | EventKind::Digest => format!("{}{}", body, times(self.count)),
+        }
+    }
+}
+
+/// Boils a pile of withheld events down to one line, e.g. "3 PMs from alice, bob; carol joined
+/// #hello twice".
+pub fn summarize(events: &[NotificationEvent]) -> String {
+    let mut groups: Vec<Group> = Vec::new();
+
+    for event in events {
+        let idx = groups.iter().position(|group| group.includes(event));
+        let group = match idx {
+            Some(idx) => &mut groups[idx],
+            None => {
+                groups.push(Group { first: event, nicks: Vec::new(), count: 0 });
+                groups.last_mut().unwrap()
+            }
+        };
+
+        group.count += 1;
+        if !group.nicks.contains(&&*event.nick) {
+            group.nicks.push(&event.nick);
+        }
+    }
+
+    groups.iter().map(Group::describe).collect::<Vec<_>>().join("; ")
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+fn times(count: usize) -> String {
+    match count {
+        1 => String::new(),
+        2 => String::from(" twice"),
+        n => format!(" {} times", n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notifications::NotificationEvent;
+    use super::summarize;
+
+    #[test]
+    fn events_are_grouped_in_order() {
+        let events = vec![
+            NotificationEvent::pm("alice", "hi"),
+            NotificationEvent::join("carol", "#hello"),
+            NotificationEvent::pm("bob", "you there?"),
+            NotificationEvent::pm("alice", "hello?"),
+            NotificationEvent::join("carol", "#hello"),
+        ];
+
+        assert_eq!("3 PMs from alice, bob; carol joined #hello twice", summarize(&events));
+    }
+
+    #[test]
+    fn joins_in_different_channels_are_separate() {
+        let events = vec![
+            NotificationEvent::join("carol", "#hello"),
+            NotificationEvent::join("carol", "#rust"),
+            NotificationEvent::mention("dave", "#rust", "ping"),
+        ];
+
+        assert_eq!("carol joined #hello; carol joined #rust; 1 mention in #rust from dave", summarize(&events));
+    }
+}
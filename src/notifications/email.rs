@@ -0,0 +1,279 @@
+use config;
+use crypto::base64;
+use notifications::{NotificationEvent, NotificationFailure, NotificationResult, NotificationSink};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+#[cfg(feature = "ssl")]
+use native_tls::{TlsConnector, TlsStream};
+
+/// How long the relay gets to answer, or take what we send, before the send counts as failed.
+const TIMEOUT_SECS: u64 = 30;
+
+/// Delivers notifications by email, speaking just enough SMTP to hand a message to a relay.
+pub struct Email {
+    server: String,
+    starttls: bool,
+    credentials: Option<(String, String)>,
+    from: String,
+}
+
+impl Email {
+    pub fn with_config(config: &config::Email) -> Email {
+        Email {
+            server: config.server.clone(),
+            starttls: config.starttls,
+            credentials: match (config.username.as_ref(), config.password.as_ref()) {
+                (Some(username), Some(password)) => Some((username.clone(), password.clone())),
+                _ => None,
+            },
+            from: config.from.clone(),
+        }
+    }
+
+    fn deliver(&self, recipient: &str, event: &NotificationEvent) -> io::Result<()> {
+        let stream = TcpStream::connect(&*self.server)?;
+        stream.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SECS)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(TIMEOUT_SECS)))?;
+
+        let mut session = Session::new(Connection::Plain(stream));
+        session.expect(220)?;
+        session.command(&format!("EHLO {}", hostname(&self.from)), 250)?;
+
+        if self.starttls {
+            session.command("STARTTLS", 220)?;
+            session = session.starttls(host(&self.server))?;
+            session.command(&format!("EHLO {}", hostname(&self.from)), 250)?;
+        }
+
+        if let Some((ref username, ref password)) = self.credentials {
+            let token = base64(format!("\0{}\0{}", username, password).as_bytes());
+            session.command(&format!("AUTH PLAIN {}", token), 235)?;
+        }
+
+        session.command(&format!("MAIL FROM:<{}>", self.from), 250)?;
+        session.command(&format!("RCPT TO:<{}>", recipient), 250)?;
+        session.command("DATA", 354)?;
+        session.command(&self.format_message(recipient, event), 250)?;
+        session.command("QUIT", 221)
+    }
+
+    fn format_message(&self, recipient: &str, event: &NotificationEvent) -> String {
+        let mut data = format!(
+            "From: {}\r\nTo: {}\r\nDate: {}\r\nSubject: {}\r\n\r\n",
+            self.from,
+            recipient,
+            event.time.to_rfc2822(),
+            event.subject().replace(|c| c == '\r' || c == '\n', " ")
+        );
+
+        let mut body = event.context.join("\n");
+        if !body.is_empty() {
+            body.push_str("\n");
+        }
+        body.push_str(&event.to_string());
+
+        // A line consisting of a lone `.` ends the message, so leading dots get doubled.
+        for line in body.lines() {
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+            data.push_str("\r\n");
+        }
+
+        data.push('.');
+        data
+    }
+}
+
+impl NotificationSink for Email {
+    fn send(&self, recipient: &str, event: &NotificationEvent) -> NotificationResult {
+        self.deliver(recipient, event).map_err(|e| NotificationFailure::Failure(e.to_string()))
+    }
+}
+
+enum Connection {
+    Plain(TcpStream),
+    #[cfg(feature = "ssl")]
+    Tls(TlsStream<TcpStream>),
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Connection::Plain(ref mut stream) => stream.read(buf),
+            #[cfg(feature = "ssl")]
+            Connection::Tls(ref mut stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Connection::Plain(ref mut stream) => stream.write(buf),
+            #[cfg(feature = "ssl")]
+            Connection::Tls(ref mut stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Connection::Plain(ref mut stream) => stream.flush(),
+            #[cfg(feature = "ssl")]
+            Connection::Tls(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+struct Session {
+    connection: BufReader<Connection>,
+}
+
+impl Session {
+    fn new(connection: Connection) -> Session {
+        Session { connection: BufReader::new(connection) }
+    }
+
+    fn command(&mut self, command: &str, expected: u16) -> io::Result<()> {
+        write!(self.connection.get_mut(), "{}\r\n", command)?;
+        self.connection.get_mut().flush()?;
+        self.expect(expected)
+    }
+
+    /// Reads a (possibly multi-line) reply and checks its status code.
+    fn expect(&mut self, expected: u16) -> io::Result<()> {
+        loop {
+            let mut line = String::new();
+            if self.connection.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "smtp server hung up"));
+            }
+
+            let code = if line.len() >= 3 && line.is_char_boundary(3) {
+                line[..3].parse::<u16>().ok()
+            } else {
+                None
+            };
+            let last = line.as_bytes().get(3) != Some(&b'-');
+
+            match code {
+                Some(code) if code != expected => {
+                    return Err(io::Error::new(io::ErrorKind::Other, format!("smtp: {}", line.trim_right())));
+                }
+                Some(_) if last => return Ok(()),
+                Some(_) => (),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("smtp: {}", line.trim_right()))),
+            }
+        }
+    }
+
+    #[cfg(feature = "ssl")]
+    fn starttls(self, domain: &str) -> io::Result<Session> {
+        let stream = match self.connection.into_inner() {
+            Connection::Plain(stream) => stream,
+            Connection::Tls(_) => return Err(io::Error::new(io::ErrorKind::Other, "already using tls")),
+        };
+
+        let connector = TlsConnector::builder()
+            .and_then(|builder| builder.build())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+        let stream = connector.connect(domain, stream)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))?;
+
+        Ok(Session::new(Connection::Tls(stream)))
+    }
+
+    #[cfg(not(feature = "ssl"))]
+    fn starttls(self, _domain: &str) -> io::Result<Session> {
+        Err(io::Error::new(io::ErrorKind::Other, "built without the ssl feature"))
+    }
+}
+
+#[inline]
+fn host(server: &str) -> &str {
+    server.split(':').next().unwrap_or(server)
+}
+
+#[inline]
+fn hostname(address: &str) -> &str {
+    address.rsplit('@').next().unwrap_or("localhost")
+}
+
+#[cfg(test)]
+mod tests {
+    use config;
+    use notifications::{NotificationEvent, NotificationSink};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use super::Email;
+
+    #[test]
+    fn message_is_delivered_to_smtp_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        // Just enough of an SMTP server to accept one message and tell us what it saw.
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut transcript = Vec::new();
+
+            writer.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+
+                let line = line.trim_right().to_owned();
+                let reply: &[u8] = if in_data {
+                    if line == "." { in_data = false; b"250 queued\r\n" } else { b"" }
+                } else if line.starts_with("EHLO") {
+                    b"250-localhost\r\n250 AUTH PLAIN\r\n"
+                } else if line.starts_with("AUTH") {
+                    b"235 ok\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    transcript.push(line);
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+
+                transcript.push(line);
+                writer.write_all(reply).unwrap();
+            }
+
+            transcript
+        });
+
+        let email = Email::with_config(&config::Email {
+            server: address,
+            starttls: false,
+            username: Some("user".to_owned()),
+            password: Some("pass".to_owned()),
+            from: "watcher@example.org".to_owned(),
+            recipient: "owner@example.org".to_owned(),
+        });
+
+        email.send("owner@example.org", &NotificationEvent::pm("alice", "hi\n.hidden")).unwrap();
+        let transcript = server.join().unwrap();
+
+        assert!(transcript.contains(&"AUTH PLAIN AHVzZXIAcGFzcw==".to_owned()));
+        assert!(transcript.contains(&"MAIL FROM:<watcher@example.org>".to_owned()));
+        assert!(transcript.contains(&"RCPT TO:<owner@example.org>".to_owned()));
+        assert!(transcript.contains(&"Subject: PM from alice".to_owned()));
+        assert!(transcript.contains(&"PM from alice: hi".to_owned()));
+        assert!(transcript.contains(&"..hidden".to_owned()));
+        assert_eq!(Some(&"QUIT".to_owned()), transcript.last());
+    }
+}
@@ -0,0 +1,156 @@
+use chrono::{DateTime, UTC};
+use notifications::digest;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    Join,
+    Pm,
+    Mention,
+    Part,
+    Health,
+    Digest,
+}
+
+impl EventKind {
+    /// The name used for this kind of event in config files and webhook payloads.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EventKind::Join => "join",
+            EventKind::Pm => "pm",
+            EventKind::Mention => "mention",
+            EventKind::Part => "part",
+            EventKind::Health => "health",
+            EventKind::Digest => "digest",
+        }
+    }
+
+    /// The other way round from `name`.
+    pub fn from_name(name: &str) -> Option<EventKind> {
+        match name {
+            "join" => Some(EventKind::Join),
+            "pm" => Some(EventKind::Pm),
+            "mention" => Some(EventKind::Mention),
+            "part" => Some(EventKind::Part),
+            "health" => Some(EventKind::Health),
+            "digest" => Some(EventKind::Digest),
+            _ => None,
+        }
+    }
+}
+
+/// Something the owner should hear about. Each sink decides how to present it.
+#[derive(Clone, Debug)]
+pub struct NotificationEvent {
+    pub kind: EventKind,
+    pub nick: String,
+    pub channel: Option<String>,
+    pub body: Option<String>,
+    /// Whatever was said just before, for events that come out of channel chat.
+    pub context: Vec<String>,
+    pub time: DateTime<UTC>,
+    /// How a configured template says this reads, in place of the usual line.
+    pub text: Option<String>,
+}
+
+impl NotificationEvent {
+    pub fn join(nick: &str, channel: &str) -> NotificationEvent {
+        NotificationEvent::new(EventKind::Join, nick, Some(channel), None)
+    }
+
+    pub fn pm(nick: &str, body: &str) -> NotificationEvent {
+        NotificationEvent::new(EventKind::Pm, nick, None, Some(body))
+    }
+
+    pub fn mention(nick: &str, channel: &str, body: &str) -> NotificationEvent {
+        NotificationEvent::new(EventKind::Mention, nick, Some(channel), Some(body))
+    }
+
+    pub fn part(nick: &str, channel: &str, reason: Option<&str>) -> NotificationEvent {
+        NotificationEvent::new(EventKind::Part, nick, Some(channel), reason)
+    }
+
+    /// Something about the bot itself, rather than anyone on IRC.
+    pub fn health(body: &str) -> NotificationEvent {
+        NotificationEvent::new(EventKind::Health, "", None, Some(body))
+    }
+
+    pub fn with_context(mut self, context: Vec<String>) -> NotificationEvent {
+        self.context = context;
+        self
+    }
+
+    pub fn with_text(mut self, text: String) -> NotificationEvent {
+        self.text = Some(text);
+        self
+    }
+
+    /// Everything that was held back, rolled into one.
+    pub fn digest(events: &[NotificationEvent]) -> NotificationEvent {
+        NotificationEvent::new(EventKind::Digest, "", None, Some(&digest::summarize(events)))
+    }
+
+    fn new(kind: EventKind, nick: &str, channel: Option<&str>, body: Option<&str>) -> NotificationEvent {
+        NotificationEvent {
+            kind: kind,
+            nick: nick.to_owned(),
+            channel: channel.map(|channel| channel.to_owned()),
+            body: body.map(|body| body.to_owned()),
+            context: Vec::new(),
+            time: UTC::now(),
+            text: None,
+        }
+    }
+
+    /// A short headline, e.g. for an email subject.
+    pub fn subject(&self) -> String {
+        let channel = self.channel.as_ref().map_or("", |channel| &**channel);
+        match self.kind {
+            EventKind::Join => format!("{} joined {}", self.nick, channel),
+            EventKind::Pm => format!("PM from {}", self.nick),
+            EventKind::Mention => format!("{} mentioned you in {}", self.nick, channel),
+            EventKind::Part => format!("{} left {}", self.nick, channel),
+            EventKind::Health => String::from("watcher status"),
+            EventKind::Digest => String::from("notifications you missed"),
+        }
+    }
+}
+
+/// The whole event on one line, as you'd want it in a text message.
+impl fmt::Display for NotificationEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref text) = self.text {
+            return f.write_str(text);
+        }
+
+        let channel = self.channel.as_ref().map_or("", |channel| &**channel);
+        let body = self.body.as_ref().map_or("", |body| &**body);
+
+        match self.kind {
+            EventKind::Join => write!(f, "{} has joined {}", self.nick, channel),
+            EventKind::Pm => write!(f, "PM from {}: {}", self.nick, body),
+            EventKind::Mention => write!(f, "{} in {}: {}", self.nick, channel, body),
+            EventKind::Part if self.body.is_some() => write!(f, "{} has left {} ({})", self.nick, channel, body),
+            EventKind::Part => write!(f, "{} has left {}", self.nick, channel),
+            EventKind::Health => write!(f, "watcher: {}", body),
+            EventKind::Digest => write!(f, "missed: {}", body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NotificationEvent;
+
+    #[test]
+    fn events_format_as_before() {
+        assert_eq!("alice has joined #hello", NotificationEvent::join("alice", "#hello").to_string());
+        assert_eq!("PM from alice: hi", NotificationEvent::pm("alice", "hi").to_string());
+    }
+
+    #[test]
+    fn part_reasons_are_optional() {
+        assert_eq!("bob has left #hello", NotificationEvent::part("bob", "#hello", None).to_string());
+        assert_eq!("bob has left #hello (lunch)", NotificationEvent::part("bob", "#hello", Some("lunch")).to_string());
+    }
+}
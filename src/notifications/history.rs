@@ -0,0 +1,402 @@
+use chrono::{DateTime, UTC};
+use mask::Mask;
+use notifications::{Delivery, NotificationEvent, NotificationFailure};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+
+/// How many records are kept in memory.
+const RETAINED: usize = 1000;
+
+/// How long the file gets before it's cut back to just the records kept in memory.
+const TRIM_AT: usize = 10 * RETAINED;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Sent,
+    RecentlyNotified,
+    Throttled,
+    Quiet,
+    Failed(String),
+}
+
+impl Outcome {
+    fn name(&self) -> &'static str {
+        match *self {
+            Outcome::Sent => "sent",
+            Outcome::RecentlyNotified => "recently-notified",
+            Outcome::Throttled => "throttled",
+            Outcome::Quiet => "quiet",
+            Outcome::Failed(_) => "failed",
+        }
+    }
+
+    fn withheld(&self) -> bool {
+        *self == Outcome::RecentlyNotified || *self == Outcome::Throttled || *self == Outcome::Quiet
+    }
+}
+
+impl<'a> From<&'a NotificationFailure> for Outcome {
+    fn from(failure: &NotificationFailure) -> Outcome {
+        match *failure {
+            NotificationFailure::RecentlyNotified => Outcome::RecentlyNotified,
+            NotificationFailure::Throttled(_) => Outcome::Throttled,
+            NotificationFailure::Quiet(_) => Outcome::Quiet,
+            NotificationFailure::Failure(ref e) => Outcome::Failed(e.clone()),
+        }
+    }
+}
+
+/// One attempt to notify someone. Notifications that were withheld never reached a sink, so
+/// they have no sink or recipient.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub time: DateTime<UTC>,
+    pub event: String,
+    pub nick: String,
+    pub sink: Option<String>,
+    pub recipient: Option<String>,
+    pub outcome: Outcome,
+}
+
+impl Record {
+    pub fn delivered(event: &NotificationEvent, delivery: &Delivery) -> Record {
+        Record {
+            time: event.time,
+            event: event.kind.name().to_owned(),
+            nick: event.nick.clone(),
+            sink: Some(delivery.sink.clone()),
+            recipient: Some(delivery.recipient.clone()),
+            outcome: match delivery.result {
+                Ok(()) => Outcome::Sent,
+                Err(ref failure) => Outcome::from(failure),
+            },
+        }
+    }
+
+    pub fn withheld(event: &NotificationEvent, failure: &NotificationFailure) -> Record {
+        Record {
+            time: event.time,
+            event: event.kind.name().to_owned(),
+            nick: event.nick.clone(),
+            sink: None,
+            recipient: None,
+            outcome: Outcome::from(failure),
+        }
+    }
+
+    /// Tab-separated: time, event, nick, sink, recipient, outcome, and the error for failures.
+    fn to_line(&self) -> String {
+        let error = match self.outcome {
+            Outcome::Failed(ref e) => e.as_str(),
+            _ => "",
+        };
+
+        let time = self.time.to_rfc3339();
+        let fields = [
+            &*time,
+            &*self.event,
+            &*self.nick,
+            self.sink.as_ref().map_or("-", |sink| &**sink),
+            self.recipient.as_ref().map_or("-", |recipient| &**recipient),
+            self.outcome.name(),
+            error,
+        ];
+
+        fields.iter()
+            .map(|field| field.replace(|c| c == '\t' || c == '\r' || c == '\n', " "))
+            .collect::<Vec<_>>()
+            .join("\t")
+    }
+
+    fn parse(line: &str) -> Option<Record> {
+        let fields: Vec<_> = line.split('\t').collect();
+        if fields.len() != 7 {
+            return None;
+        }
+
+        let optional = |field: &str| if field == "-" { None } else { Some(field.to_owned()) };
+        let outcome = match fields[5] {
+            "sent" => Outcome::Sent,
+            "recently-notified" => Outcome::RecentlyNotified,
+            "throttled" => Outcome::Throttled,
+            "quiet" => Outcome::Quiet,
+            "failed" => Outcome::Failed(fields[6].to_owned()),
+            _ => return None,
+        };
+
+        DateTime::parse_from_rfc3339(fields[0]).ok().map(|time| Record {
+            time: time.with_timezone(&UTC),
+            event: fields[1].to_owned(),
+            nick: fields[2].to_owned(),
+            sink: optional(fields[3]),
+            recipient: optional(fields[4]),
+            outcome: outcome,
+        })
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.time.format("%F %T"), self.event, self.nick)?;
+
+        if let (&Some(ref sink), &Some(ref recipient)) = (&self.sink, &self.recipient) {
+            write!(f, " via {} to {}", sink, recipient)?;
+        }
+
+        match self.outcome {
+            Outcome::Failed(ref e) => write!(f, ": failed ({})", e),
+            ref outcome if outcome.withheld() => write!(f, ": withheld ({})", outcome.name()),
+            ref outcome => write!(f, ": {}", outcome.name()),
+        }
+    }
+}
+
+/// Every notification attempt, appended to a file as it happens so it survives a restart.
+pub struct History {
+    path: String,
+    records: Vec<Record>,
+    /// How many lines the file has in it.
+    lines: usize,
+}
+
+impl History {
+    /// An empty history that will be written to `path`.
+    pub fn new(path: &str) -> History {
+        History {
+            path: path.to_owned(),
+            records: Vec::new(),
+            lines: 0,
+        }
+    }
+
+    /// Reads the history file, skipping any lines that don't make sense. A missing file is just
+    /// an empty history.
+    pub fn open(path: &str) -> io::Result<History> {
+        let mut history = History::new(path);
+
+        let file = match File::open(path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(history),
+            Err(e) => return Err(e),
+            Ok(file) => file,
+        };
+
+        for line in BufReader::new(file).lines() {
+            history.lines += 1;
+            if let Some(record) = Record::parse(&line?) {
+                history.push(record);
+            }
+        }
+
+        Ok(history)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn record(&mut self, record: Record) {
+        let file = OpenOptions::new().write(true).create(true).append(true).open(&self.path);
+        match file {
+            Err(e) => println!("{:?}", e),
+            Ok(mut file) => {
+                if writeln!(file, "{}", record.to_line()).is_ok() {
+                    self.lines += 1;
+                }
+            }
+        }
+
+        self.push(record);
+
+        if self.lines >= TRIM_AT {
+            if let Err(e) = self.trim() {
+                println!("unable to trim notification history {}: {:?}", self.path, e);
+            }
+        }
+    }
+
+    /// Rewrites the file with just the records kept in memory. The new file is written beside
+    /// the old one and moved over it, so a failure part way leaves the old one alone.
+    fn trim(&mut self) -> io::Result<()> {
+        let trimmed = format!("{}.trim", self.path);
+        {
+            let mut file = File::create(&trimmed)?;
+            for record in &self.records {
+                writeln!(file, "{}", record.to_line())?;
+            }
+        }
+
+        fs::rename(&trimmed, &self.path)?;
+        self.lines = self.records.len();
+        Ok(())
+    }
+
+    fn push(&mut self, record: Record) {
+        self.records.push(record);
+        if self.records.len() > RETAINED {
+            let excess = self.records.len() - RETAINED;
+            self.records.drain(..excess);
+        }
+    }
+
+    /// Every notification that got through to someone.
+    pub fn sent<'a>(&'a self) -> impl Iterator<Item = &'a Record> {
+        self.records.iter().filter(|record| record.outcome == Outcome::Sent)
+    }
+
+    /// When each nick last got a notification through to each sink and recipient.
+    pub fn last_sent(&self) -> HashMap<(String, String, String), DateTime<UTC>> {
+        let mut last_sent = HashMap::new();
+        for record in self.sent() {
+            if let (&Some(ref sink), &Some(ref recipient)) = (&record.sink, &record.recipient) {
+                last_sent.insert((record.nick.clone(), sink.clone(), recipient.clone()), record.time);
+            }
+        }
+        last_sent
+    }
+
+    /// The most recent records matching the filter, oldest first.
+    pub fn search(&self, filter: &Filter) -> Vec<&Record> {
+        let mut found: Vec<_> = self.records.iter().rev()
+            .filter(|record| filter.matches(record))
+            .take(filter.limit)
+            .collect();
+
+        found.reverse();
+        found
+    }
+}
+
+/// Narrows down a `.messages` report. Each argument is an outcome (`sent`, `failed`,
+/// `throttled`, `quiet`, `recently-notified`, or `withheld` for any of the last three), an
+/// event, a sink, a count, or a nick mask.
+#[derive(Debug, PartialEq)]
+pub struct Filter {
+    outcome: Option<String>,
+    event: Option<String>,
+    sink: Option<String>,
+    nick: Option<Mask>,
+    limit: usize,
+}
+
+impl Filter {
+    pub fn parse(args: &[String]) -> Filter {
+        let mut filter = Filter {
+            outcome: None,
+            event: None,
+            sink: None,
+            nick: None,
+            limit: 20,
+        };
+
+        for arg in args {
+            match &**arg {
+                "sent" | "failed" | "throttled" | "quiet" | "recently-notified" | "withheld" => filter.outcome = Some(arg.clone()),
+                "join" | "pm" | "mention" | "part" | "health" | "digest" => filter.event = Some(arg.clone()),
+                "sms" | "email" | "webhook" => filter.sink = Some(arg.clone()),
+                _ => match arg.parse() {
+                    Ok(limit) => filter.limit = limit,
+                    Err(_) => filter.nick = Some(Mask::new(&**arg)),
+                },
+            }
+        }
+
+        filter
+    }
+
+    fn matches(&self, record: &Record) -> bool {
+        let outcome = match self.outcome {
+            None => true,
+            Some(ref outcome) if outcome == "withheld" => record.outcome.withheld(),
+            Some(ref outcome) => outcome == record.outcome.name(),
+        };
+
+        outcome
+            && self.event.as_ref().map_or(true, |event| *event == record.event)
+            && self.sink.as_ref().map_or(true, |sink| record.sink.as_ref() == Some(sink))
+            && self.nick.as_ref().map_or(true, |nick| nick.matches(&record.nick))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, UTC};
+    use std::env;
+    use std::fs;
+    use super::{Filter, History, Outcome, Record, RETAINED, TRIM_AT};
+
+    #[test]
+    fn records_survive_a_round_trip() {
+        let failed = record("alice", Some("sms"), Outcome::Failed("no\tsignal".to_owned()));
+        let parsed = Record::parse(&failed.to_line()).unwrap();
+
+        assert_eq!("sms", parsed.sink.unwrap());
+        assert_eq!(Outcome::Failed("no signal".to_owned()), parsed.outcome);
+
+        let withheld = record("alice", None, Outcome::Throttled);
+        assert_eq!(Some(withheld.clone()), Record::parse(&withheld.to_line()));
+    }
+
+    #[test]
+    fn the_file_is_cut_back_once_it_gets_long() {
+        let path = env::temp_dir().join("watcher-test-history.log").to_string_lossy().into_owned();
+        fs::remove_file(&path).ok();
+
+        let mut history = History::new(&path);
+        for _ in 0..TRIM_AT {
+            history.record(record("alice", Some("sms"), Outcome::Sent));
+        }
+
+        let history = History::open(&path).unwrap();
+        assert_eq!(RETAINED, history.lines);
+        assert_eq!(RETAINED, history.records.len());
+    }
+
+    #[test]
+    fn garbage_is_not_a_record() {
+        assert_eq!(None, Record::parse("alice has joined #hello"));
+    }
+
+    #[test]
+    fn reports_are_filtered_and_limited() {
+        let mut history = History::new("unused");
+        history.push(record("alice", Some("sms"), Outcome::Sent));
+        history.push(record("bob", None, Outcome::RecentlyNotified));
+        history.push(record("alice", Some("email"), Outcome::Failed("refused".to_owned())));
+        history.push(record("alice", None, Outcome::Throttled));
+
+        assert_eq!(4, history.search(&filter(&[])).len());
+        assert_eq!(2, history.search(&filter(&["withheld"])).len());
+        assert_eq!(1, history.search(&filter(&["alice", "email"])).len());
+
+        let latest = history.search(&filter(&["alice", "1"]));
+        assert_eq!(vec![Outcome::Throttled], latest.iter().map(|record| record.outcome.clone()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn only_successes_count_as_sent() {
+        let mut history = History::new("unused");
+        history.push(record("alice", Some("sms"), Outcome::Sent));
+        history.push(record("bob", Some("sms"), Outcome::Failed("refused".to_owned())));
+
+        let last_sent = history.last_sent();
+        assert!(last_sent.contains_key(&("alice".to_owned(), "sms".to_owned(), "owner".to_owned())));
+        assert!(!last_sent.contains_key(&("bob".to_owned(), "sms".to_owned(), "owner".to_owned())));
+    }
+
+    fn record(nick: &str, sink: Option<&str>, outcome: Outcome) -> Record {
+        Record {
+            time: UTC.ymd(2017, 6, 1).and_hms(12, 0, 0),
+            event: "pm".to_owned(),
+            nick: nick.to_owned(),
+            sink: sink.map(|sink| sink.to_owned()),
+            recipient: sink.map(|_| "owner".to_owned()),
+            outcome: outcome,
+        }
+    }
+
+    fn filter(args: &[&str]) -> Filter {
+        Filter::parse(&args.iter().map(|&arg| arg.to_owned()).collect::<Vec<_>>())
+    }
+}
@@ -0,0 +1,510 @@
+mod digest;
+mod email;
+mod event;
+mod history;
+mod pending;
+mod router;
+mod schedule;
+mod sms;
+mod template;
+#[cfg(test)]
+pub mod testing;
+mod throttle;
+mod webhook;
+mod worker;
+
+use chrono::{self, DateTime, UTC};
+use config;
+use self::worker::{Job, Report};
+use std::cmp;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+pub use self::email::Email;
+pub use self::event::{EventKind, NotificationEvent};
+pub use self::history::{Filter, History, Outcome, Record};
+pub use self::router::{Delivery, Recipient, Route, Router, Target};
+pub use self::schedule::Schedule;
+pub use self::sms::Sms;
+pub use self::template::Templates;
+pub use self::throttle::Throttle;
+pub use self::webhook::Webhook;
+pub use self::worker::{Backoff, DeadLetter};
+
+pub type NotificationResult = Result<(), NotificationFailure>;
+
+/// Either the notification was withheld altogether, or here is how each sink fared with it.
+pub type DeliveryResult = Result<Vec<Delivery>, NotificationFailure>;
+
+#[derive(Debug)]
+pub enum NotificationFailure {
+    RecentlyNotified,
+    /// Sending has to wait until the given time.
+    Throttled(DateTime<UTC>),
+    /// It's the recipient's quiet hours. If it's being held for later, this says until when.
+    Quiet(Option<DateTime<UTC>>),
+    Failure(String),
+}
+
+pub trait NotificationSink {
+    fn send(&self, recipient: &str, event: &NotificationEvent) -> NotificationResult;
+}
+
+/// A notification kept back from one destination, to go out in a digest once it's `ready`.
+pub struct Held {
+    event: NotificationEvent,
+    sink: String,
+    recipient: String,
+    ready: DateTime<UTC>,
+}
+
+pub struct NotificationService {
+    router: Router,
+    history: History,
+    /// When each nick was last notified to each sink and recipient.
+    sent: HashMap<(String, String, String), Instant>,
+    frequency: Duration,
+    throttle: Throttle,
+    schedule: Schedule,
+    held: Vec<Held>,
+    digest_interval: chrono::Duration,
+    templates: Templates,
+    jobs: Sender<Job>,
+    reports: Receiver<Report>,
+    /// Sends queued or waiting on a retry, that the worker hasn't finished with yet.
+    in_flight: usize,
+    dead: Vec<DeadLetter>,
+}
+
+/// Puts together a `NotificationService`. Anything not set is as it would be with nothing in
+/// the config file for it, except `frequency`, which is zero.
+pub struct Builder {
+    router: Router,
+    history: History,
+    frequency: Duration,
+    throttle: Throttle,
+    schedule: Schedule,
+    digest_interval: Duration,
+    backoff: Backoff,
+    templates: Templates,
+}
+
+impl Builder {
+    /// How often any one nick may set off notifications to the same recipient.
+    pub fn frequency(mut self, frequency: Duration) -> Builder {
+        self.frequency = frequency;
+        self
+    }
+
+    pub fn throttle(mut self, throttle: Throttle) -> Builder {
+        self.throttle = throttle;
+        self
+    }
+
+    pub fn schedule(mut self, schedule: Schedule) -> Builder {
+        self.schedule = schedule;
+        self
+    }
+
+    /// How long to hold on to notifications withheld for being too frequent.
+    pub fn digest_interval(mut self, digest_interval: Duration) -> Builder {
+        self.digest_interval = digest_interval;
+        self
+    }
+
+    pub fn backoff(mut self, backoff: Backoff) -> Builder {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn templates(mut self, templates: Templates) -> Builder {
+        self.templates = templates;
+        self
+    }
+
+    pub fn build(self) -> NotificationService {
+        let Builder { router, history, frequency, mut throttle, schedule, digest_interval, backoff, templates } = self;
+
+        // Pick up where we left off, so a restart doesn't mean everyone gets notified again.
+        // Anything older than every frequency and limit window can't hold anything back.
+        let relevant = cmp::max(cmp::max(frequency, throttle.longest()), router.longest_frequency().unwrap_or(frequency));
+        let sent = history.last_sent().into_iter()
+            .filter_map(|(key, time)| to_instant(time, relevant).map(|instant| (key, instant)))
+            .collect();
+
+        for record in history.sent() {
+            if let (Some(instant), &Some(ref sink), &Some(ref recipient)) = (to_instant(record.time, relevant), &record.sink, &record.recipient) {
+                throttle.record(sink, recipient, instant);
+            }
+        }
+
+        // Whatever was still held back or given up on when we last stopped.
+        let (held, dead) = match pending::load(&pending_path(&history)) {
+            Ok(pending) => pending,
+            Err(e) => {
+                println!("unable to read held notifications: {:?}", e);
+                (Vec::new(), Vec::new())
+            }
+        };
+
+        let (jobs, reports) = worker::spawn(backoff);
+
+        NotificationService {
+            router: router,
+            history: history,
+            sent: sent,
+            frequency: frequency,
+            throttle: throttle,
+            schedule: schedule,
+            held: held,
+            digest_interval: chrono::Duration::from_std(digest_interval).unwrap_or_else(|_| chrono::Duration::zero()),
+            templates: templates,
+            jobs: jobs,
+            reports: reports,
+            in_flight: 0,
+            dead: dead,
+        }
+    }
+}
+
+impl NotificationService {
+    pub fn builder(router: Router, history: History) -> Builder {
+        let defaults = config::Notifications::default();
+
+        Builder {
+            router: router,
+            history: history,
+            frequency: Duration::from_secs(0),
+            throttle: Throttle::with_config(&defaults.limits),
+            schedule: Schedule::with_config(&defaults.quiet_hours),
+            digest_interval: Duration::from_secs(defaults.digest_interval),
+            backoff: Backoff::with_config(&defaults),
+            templates: Templates::with_config(&defaults, ""),
+        }
+    }
+
+    /// Pass an event on to whichever sinks it's routed to, unless we've been too chatty lately.
+    /// Anything withheld is kept for a later digest. Deliveries that come back `Ok` have only
+    /// been queued; `collect` says how they actually went.
+    pub fn notify(&mut self, event: &NotificationEvent) -> DeliveryResult {
+        self.dispatch(event, true)
+    }
+
+    /// Like `notify`, but only for the recipients watching for this: nobody else asked about it.
+    pub fn notify_recipients(&mut self, event: &NotificationEvent) -> DeliveryResult {
+        self.dispatch(event, false)
+    }
+
+    fn dispatch(&mut self, event: &NotificationEvent, everyone: bool) -> DeliveryResult {
+        let mut deliveries = Vec::new();
+
+        // Scoped so the router can be let go of before anything is held.
+        {
+            let destinations = if everyone {
+                self.router.destinations(event)
+            } else {
+                self.router.recipient_destinations(event)
+            };
+
+            for (target, recipient) in destinations {
+                let key = (event.nick.clone(), target.name().to_owned(), recipient.to_owned());
+                let frequency = self.router.frequency(target.name(), recipient).unwrap_or(self.frequency);
+
+                let delivery = if self.sent.get(&key).map_or(false, |last| last.elapsed() <= frequency) {
+                    Delivery {
+                        sink: target.name().to_owned(),
+                        recipient: recipient.to_owned(),
+                        result: Err(NotificationFailure::RecentlyNotified),
+                    }
+                } else {
+                    let event = self.templates.apply(event, target.name());
+                    attempt(target, recipient, &event, &mut self.throttle, &self.schedule, &self.jobs)
+                };
+
+                if delivery.result.is_ok() {
+                    self.sent.insert(key, Instant::now());
+                    self.in_flight += 1;
+                }
+                deliveries.push(delivery);
+            }
+        }
+
+        let ready = event.time + self.digest_interval;
+
+        // Nobody heard about it, for the same reason everywhere; that's one thing to report.
+        if !deliveries.is_empty() && deliveries.iter().all(|delivery| recently_notified(&delivery.result)) {
+            let failure = NotificationFailure::RecentlyNotified;
+            self.history.record(Record::withheld(event, &failure));
+
+            for delivery in deliveries {
+                self.hold(event.clone(), delivery.sink, delivery.recipient, ready);
+            }
+            return Err(failure);
+        }
+
+        // Whatever was queued gets recorded once the worker has had a go at it.
+        for delivery in deliveries.iter().filter(|delivery| delivery.result.is_err()) {
+            self.history.record(Record::delivered(event, delivery));
+
+            let until = if recently_notified(&delivery.result) { Some(ready) } else { held_until(&delivery.result) };
+            if let Some(until) = until {
+                self.hold(event.clone(), delivery.sink.clone(), delivery.recipient.clone(), until);
+            }
+        }
+
+        Ok(deliveries)
+    }
+
+    /// Sends a digest to every destination with something held back for it, if it's time.
+    /// Nothing ticks on its own in here, so this needs calling every so often.
+    pub fn flush(&mut self) -> Option<DeliveryResult> {
+        let now = UTC::now();
+        let mut due: Vec<(String, String)> = Vec::new();
+        for held in self.held.iter().filter(|held| held.ready <= now) {
+            let destination = (held.sink.clone(), held.recipient.clone());
+            if !due.contains(&destination) {
+                due.push(destination);
+            }
+        }
+
+        if due.is_empty() {
+            return None;
+        }
+
+        let mut deliveries = Vec::new();
+        for (sink, recipient) in due {
+            let (events, rest): (Vec<Held>, Vec<Held>) = self.held.drain(..)
+                .partition(|held| held.sink == sink && held.recipient == recipient);
+            self.held = rest;
+
+            let digest = NotificationEvent::digest(&events.iter().map(|held| held.event.clone()).collect::<Vec<_>>());
+            let delivery = match self.router.target(&sink) {
+                // Held over from before a restart, and the sink has been taken out of the config
+                // since. There's no sending it, but it goes down as a failure like anything else.
+                None => {
+                    let error = format!("{} is no longer configured", sink);
+                    self.dead.push(DeadLetter {
+                        time: now,
+                        event: digest.clone(),
+                        sink: sink.clone(),
+                        recipient: recipient.clone(),
+                        attempts: 0,
+                        error: error.clone(),
+                    });
+
+                    Delivery {
+                        sink: sink.clone(),
+                        recipient: recipient.clone(),
+                        result: Err(NotificationFailure::Failure(error)),
+                    }
+                }
+                Some(target) => {
+                    let digest = self.templates.apply(&digest, &sink);
+                    attempt(target, &recipient, &digest, &mut self.throttle, &self.schedule, &self.jobs)
+                }
+            };
+
+            if delivery.result.is_ok() {
+                self.in_flight += 1;
+            } else {
+                self.history.record(Record::delivered(&digest, &delivery));
+            }
+
+            // Still no room, or still quiet; keep everything for next time.
+            if let Some(ready) = held_until(&delivery.result) {
+                for held in events {
+                    self.hold(held.event, sink.clone(), recipient.clone(), ready);
+                }
+            }
+
+            deliveries.push(delivery);
+        }
+
+        Some(Ok(deliveries))
+    }
+
+    fn hold(&mut self, event: NotificationEvent, sink: String, recipient: String, ready: DateTime<UTC>) {
+        self.held.push(Held {
+            event: event,
+            sink: sink,
+            recipient: recipient,
+            ready: ready,
+        });
+    }
+
+    /// Picks up whatever the delivery worker has done since last time: every attempt goes in
+    /// the history, and anything it gave up on becomes a dead letter. Like `flush`, this needs
+    /// calling every so often.
+    pub fn collect(&mut self) -> Vec<Delivery> {
+        let mut deliveries = Vec::new();
+
+        while let Ok(report) = self.reports.try_recv() {
+            if let Some(delivery) = self.receive(report) {
+                deliveries.push(delivery);
+            }
+        }
+
+        deliveries
+    }
+
+    /// Stops taking new work and gives the worker up to `wait` to finish what it has, including
+    /// one last try at anything waiting on a retry, recording everything as it comes back.
+    /// Anything still held for a digest, and the dead letters, are saved for the next run.
+    /// Returns how many sends were still unfinished when time ran out.
+    pub fn shutdown(&mut self, wait: Duration) -> usize {
+        // Once nothing can send it any more jobs, the worker finishes up and stops.
+        let (closed, _) = mpsc::channel();
+        drop(mem::replace(&mut self.jobs, closed));
+
+        let deadline = Instant::now() + wait;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            match self.reports.recv_timeout(deadline - now) {
+                Ok(report) => {
+                    self.receive(report);
+                }
+                Err(_) => break,
+            }
+        }
+
+        if let Err(e) = pending::save(&pending_path(&self.history), &self.held, &self.dead) {
+            println!("unable to save held notifications: {:?}", e);
+        }
+
+        self.in_flight
+    }
+
+    /// Records one of the worker's reports, keeping it if it's a dead letter. Anything else is
+    /// handed back.
+    fn receive(&mut self, report: Report) -> Option<Delivery> {
+        self.history.record(Record::delivered(&report.event, &report.delivery));
+
+        if report.retry.is_none() && self.in_flight > 0 {
+            self.in_flight -= 1;
+        }
+
+        if report.is_dead() {
+            // Just enough to see what went missing lately.
+            if self.dead.len() >= MAX_DEAD_LETTERS {
+                self.dead.remove(0);
+            }
+            self.dead.push(DeadLetter::from_report(report));
+            None
+        } else {
+            Some(report.delivery)
+        }
+    }
+
+    /// Whether any recipient wants to hear about this, whoever else is watching.
+    pub fn watching(&self, event: &NotificationEvent) -> bool {
+        self.router.watching(event)
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn dead_letters(&self) -> &[DeadLetter] {
+        &self.dead
+    }
+}
+
+const MAX_DEAD_LETTERS: usize = 100;
+
+/// Held notifications and dead letters are kept next to the history between runs.
+fn pending_path(history: &History) -> String {
+    format!("{}.pending", history.path())
+}
+
+/// History is kept in wall-clock time, but the throttle works in `Instant`s. Anything from the
+/// future doesn't convert, and neither does anything older than `relevant`, or older than the
+/// clock the `Instant`s count from.
+fn to_instant(time: DateTime<UTC>, relevant: Duration) -> Option<Instant> {
+    match (UTC::now() - time).to_std() {
+        Ok(age) if age <= relevant => Instant::now().checked_sub(age),
+        _ => None,
+    }
+}
+
+/// Queues a send to one destination, if its throttle and quiet hours allow. Queued sends count
+/// against the throttle whether or not they make it in the end.
+fn attempt(
+    target: &Target,
+    recipient: &str,
+    event: &NotificationEvent,
+    throttle: &mut Throttle,
+    schedule: &Schedule,
+    jobs: &Sender<Job>,
+) -> Delivery {
+    let result = match (throttle.check(target.name(), recipient), schedule.check(target.name(), recipient, event)) {
+        (Err(until), _) => Err(NotificationFailure::Throttled(until)),
+        (Ok(()), Err(quiet)) => Err(quiet),
+        (Ok(()), Ok(())) => jobs.send(Job::new(target.clone(), recipient, event))
+            .map_err(|_| NotificationFailure::Failure(String::from("delivery worker has stopped"))),
+    };
+
+    if result.is_ok() {
+        throttle.record(target.name(), recipient, Instant::now());
+    }
+
+    Delivery {
+        sink: target.name().to_owned(),
+        recipient: recipient.to_owned(),
+        result: result,
+    }
+}
+
+fn recently_notified(result: &NotificationResult) -> bool {
+    match *result {
+        Err(NotificationFailure::RecentlyNotified) => true,
+        _ => false,
+    }
+}
+
+/// When a withheld notification should be tried again, if ever.
+fn held_until(result: &NotificationResult) -> Option<DateTime<UTC>> {
+    match *result {
+        Err(NotificationFailure::Throttled(until)) => Some(until),
+        Err(NotificationFailure::Quiet(until)) => until,
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::UTC;
+    use std::env;
+    use std::sync::mpsc;
+    use super::{pending, testing, Held, NotificationEvent};
+
+    #[test]
+    fn held_notifications_for_a_sink_thats_gone_are_failures() {
+        let (sent, received) = mpsc::channel();
+        let service = testing::recording("unconfigured", sent);
+
+        // Held for email when the bot last stopped, but email isn't configured any more.
+        let path = env::temp_dir().join("watcher-test-unconfigured.log.pending").to_string_lossy().into_owned();
+        let held = Held {
+            event: NotificationEvent::pm("alice", "hi"),
+            sink: "email".to_owned(),
+            recipient: "owner@example.org".to_owned(),
+            ready: UTC::now(),
+        };
+        pending::save(&path, &[held], &[]).unwrap();
+
+        let mut service = service.build();
+        let deliveries = service.flush().unwrap().unwrap();
+        assert_eq!(1, deliveries.len());
+        assert!(deliveries[0].result.is_err());
+        assert_eq!("email", service.dead_letters()[0].sink);
+
+        assert!(service.flush().is_none());
+        assert!(received.try_recv().is_err());
+    }
+}
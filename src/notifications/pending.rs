@@ -0,0 +1,163 @@
+use chrono::{DateTime, UTC};
+use notifications::{DeadLetter, EventKind, Held, NotificationEvent};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+
+/// Notifications still held for a digest, and the dead letters, written out at shutdown so the
+/// next run picks them back up. One tab-separated line each: `held`, when it's ready, sink and
+/// recipient; or `dead`, when it died, sink, recipient, attempts and error. Then the event's
+/// kind, time, nick, channel, body and text. Context lines aren't kept.
+pub fn save(path: &str, held: &[Held], dead: &[DeadLetter]) -> io::Result<()> {
+    if held.is_empty() && dead.is_empty() {
+        return match fs::remove_file(path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        };
+    }
+
+    let mut file = File::create(path)?;
+    for held in held {
+        let ready = held.ready.to_rfc3339();
+        writeln!(file, "{}", line(&["held", &*ready, &*held.sink, &*held.recipient], &held.event))?;
+    }
+
+    for letter in dead {
+        let (time, attempts) = (letter.time.to_rfc3339(), letter.attempts.to_string());
+        let fields = ["dead", &*time, &*letter.sink, &*letter.recipient, &*attempts, &*letter.error];
+        writeln!(file, "{}", line(&fields, &letter.event))?;
+    }
+
+    Ok(())
+}
+
+/// Reads back what `save` wrote, skipping anything that doesn't make sense, and removes the
+/// file so it's only ever picked up once. A missing file means there's nothing to pick up.
+pub fn load(path: &str) -> io::Result<(Vec<Held>, Vec<DeadLetter>)> {
+    let (mut held, mut dead) = (Vec::new(), Vec::new());
+
+    let file = match File::open(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok((held, dead)),
+        Err(e) => return Err(e),
+        Ok(file) => file,
+    };
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let fields: Vec<_> = line.split('\t').collect();
+
+        match fields[..] {
+            ["held", ready, sink, recipient, ref event..] => {
+                if let (Some(ready), Some(event)) = (time(ready), parse_event(event)) {
+                    held.push(Held {
+                        event: event,
+                        sink: sink.to_owned(),
+                        recipient: recipient.to_owned(),
+                        ready: ready,
+                    });
+                }
+            }
+            ["dead", died, sink, recipient, attempts, error, ref event..] => {
+                if let (Some(died), Ok(attempts), Some(event)) = (time(died), attempts.parse(), parse_event(event)) {
+                    dead.push(DeadLetter {
+                        time: died,
+                        event: event,
+                        sink: sink.to_owned(),
+                        recipient: recipient.to_owned(),
+                        attempts: attempts,
+                        error: error.to_owned(),
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fs::remove_file(path)?;
+    Ok((held, dead))
+}
+
+fn line(fields: &[&str], event: &NotificationEvent) -> String {
+    let time = event.time.to_rfc3339();
+    let optional = |field: &Option<String>| field.clone().unwrap_or_else(|| String::from("-"));
+    let event = [
+        event.kind.name().to_owned(),
+        time,
+        event.nick.clone(),
+        optional(&event.channel),
+        optional(&event.body),
+        optional(&event.text),
+    ];
+
+    fields.iter()
+        .map(|field| field.to_string())
+        .chain(event.iter().cloned())
+        .map(|field| field.replace(|c| c == '\t' || c == '\r' || c == '\n', " "))
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+fn parse_event(fields: &[&str]) -> Option<NotificationEvent> {
+    let optional = |field: &str| if field == "-" { None } else { Some(field.to_owned()) };
+
+    match *fields {
+        [kind, sent, nick, channel, body, text] => match (EventKind::from_name(kind), time(sent)) {
+            (Some(kind), Some(sent)) => Some(NotificationEvent {
+                kind: kind,
+                nick: nick.to_owned(),
+                channel: optional(channel),
+                body: optional(body),
+                context: Vec::new(),
+                time: sent,
+                text: optional(text),
+            }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn time(field: &str) -> Option<DateTime<UTC>> {
+    DateTime::parse_from_rfc3339(field).ok().map(|time| time.with_timezone(&UTC))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, UTC};
+    use notifications::{DeadLetter, Held, NotificationEvent};
+    use std::env;
+    use super::{load, save};
+
+    #[test]
+    fn held_notifications_and_dead_letters_survive_a_restart() {
+        let path = env::temp_dir().join("watcher-test-pending").to_string_lossy().into_owned();
+        let time = UTC.ymd(2017, 6, 1).and_hms(12, 0, 0);
+        let mut event = NotificationEvent::pm("alice", "one\ttwo");
+        event.time = time;
+
+        let held = vec![Held { event: event.clone(), sink: "sms".to_owned(), recipient: "+15551234567".to_owned(), ready: time }];
+        let dead = vec![DeadLetter {
+            time: time,
+            event: NotificationEvent::join("bob", "#hello").with_text("bob is here".to_owned()),
+            sink: "email".to_owned(),
+            recipient: "owner@example.org".to_owned(),
+            attempts: 6,
+            error: "refused".to_owned(),
+        }];
+        save(&path, &held, &dead).unwrap();
+
+        let (held, dead) = load(&path).unwrap();
+        assert_eq!(1, held.len());
+        assert_eq!("PM from alice: one two", held[0].event.to_string());
+        assert_eq!(time, held[0].ready);
+        assert_eq!("+15551234567", held[0].recipient);
+
+        assert_eq!(1, dead.len());
+        assert_eq!("bob is here", dead[0].event.to_string());
+        assert_eq!(Some("#hello".to_owned()), dead[0].event.channel);
+        assert_eq!(6, dead[0].attempts);
+
+        // Picked up once, and that's it.
+        let (held, dead) = load(&path).unwrap();
+        assert!(held.is_empty() && dead.is_empty());
+    }
+}
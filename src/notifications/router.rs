@@ -0,0 +1,260 @@
+use config;
+use mask::{self, Mask};
+use notifications::{NotificationEvent, NotificationFailure, NotificationResult, NotificationSink};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A sink, and who it should deliver to unless a route says otherwise. Copies share the sink,
+/// so one can be handed to the delivery worker.
+#[derive(Clone)]
+pub struct Target {
+    name: String,
+    sink: Arc<Mutex<Box<NotificationSink + Send>>>,
+    recipient: String,
+}
+
+impl Target {
+    pub fn new<T, S>(name: &str, sink: T, recipient: S) -> Target
+        where
+            T: NotificationSink + Send + 'static,
+            S: Into<String>,
+    {
+        Target {
+            name: name.to_owned(),
+            sink: Arc::new(Mutex::new(Box::new(sink))),
+            recipient: recipient.into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn send(&self, recipient: &str, event: &NotificationEvent) -> NotificationResult {
+        match self.sink.lock() {
+            Ok(sink) => sink.send(recipient, event),
+            Err(_) => Err(NotificationFailure::Failure(format!("{} sink is broken", self.name))),
+        }
+    }
+}
+
+/// Says which sinks get which notifications. Empty `events` or `nicks` lists match anything.
+pub struct Route {
+    events: Vec<String>,
+    nicks: Vec<Mask>,
+    sinks: Vec<String>,
+    recipient: Option<String>,
+}
+
+impl Route {
+    pub fn with_config(config: &config::Route) -> Route {
+        Route {
+            events: config.events.clone(),
+            nicks: config.nicks.clone(),
+            sinks: config.sinks.clone(),
+            recipient: config.recipient.clone(),
+        }
+    }
+
+    fn matches(&self, event: &NotificationEvent) -> bool {
+        (self.events.is_empty() || self.events.iter().any(|name| name == event.kind.name()))
+            && (self.nicks.is_empty() || mask::any_match(&self.nicks, &event.nick))
+    }
+}
+
+/// Someone from `[[recipients]]`: in effect a route of their own, with its own idea of how often
+/// is too often.
+pub struct Recipient {
+    route: Route,
+    frequency: Option<Duration>,
+}
+
+impl Recipient {
+    pub fn with_config(config: &config::Recipient) -> Recipient {
+        Recipient {
+            route: Route {
+                events: config.events.clone(),
+                nicks: config.watch_list.clone(),
+                sinks: vec![config.sink.clone()],
+                recipient: Some(config.contact.clone()),
+            },
+            frequency: config.frequency.map(Duration::from_secs),
+        }
+    }
+
+    fn is(&self, sink: &str, recipient: &str) -> bool {
+        self.route.sinks.iter().any(|name| name == sink) && self.route.recipient.as_ref().map_or(false, |contact| contact == recipient)
+    }
+}
+
+/// How one sink fared with one notification.
+#[derive(Debug)]
+pub struct Delivery {
+    pub sink: String,
+    pub recipient: String,
+    pub result: NotificationResult,
+}
+
+/// Picks which sinks each notification goes to. Without any routes, everything goes everywhere;
+/// recipients get theirs on top of that. Something only a recipient is watching for goes to
+/// that recipient alone.
+pub struct Router {
+    targets: Vec<Target>,
+    routes: Vec<Route>,
+    recipients: Vec<Recipient>,
+}
+
+impl Router {
+    pub fn new(targets: Vec<Target>, routes: Vec<Route>) -> Router {
+        Router {
+            targets: targets,
+            routes: routes,
+            recipients: Vec::new(),
+        }
+    }
+
+    pub fn with_recipients(mut self, recipients: Vec<Recipient>) -> Router {
+        self.recipients = recipients;
+        self
+    }
+
+    pub fn target(&self, name: &str) -> Option<&Target> {
+        self.targets.iter().find(|target| target.name == name)
+    }
+
+    /// Every (sink, recipient) pair this notification should go to, each only once.
+    pub fn destinations<'a>(&'a self, event: &NotificationEvent) -> Vec<(&'a Target, &'a str)> {
+        let mut destinations: Vec<(&Target, &str)> = Vec::new();
+        if self.routes.is_empty() {
+            destinations.extend(self.targets.iter().map(|target| (target, &*target.recipient)));
+        }
+
+        let routes = self.routes.iter().chain(self.recipients.iter().map(|recipient| &recipient.route));
+        self.add_routed(&mut destinations, routes, event);
+        destinations
+    }
+
+    /// Just the recipients watching for this notification, for when nobody else asked for it.
+    pub fn recipient_destinations<'a>(&'a self, event: &NotificationEvent) -> Vec<(&'a Target, &'a str)> {
+        let mut destinations: Vec<(&Target, &str)> = Vec::new();
+        self.add_routed(&mut destinations, self.recipients.iter().map(|recipient| &recipient.route), event);
+        destinations
+    }
+
+    fn add_routed<'a, I>(&'a self, destinations: &mut Vec<(&'a Target, &'a str)>, routes: I, event: &NotificationEvent)
+        where
+            I: Iterator<Item = &'a Route>,
+    {
+        for route in routes.filter(|route| route.matches(event)) {
+            for target in self.targets.iter().filter(|target| route.sinks.contains(&target.name)) {
+                let recipient = route.recipient.as_ref().map_or(&*target.recipient, |recipient| &**recipient);
+                if !destinations.iter().any(|&(t, r)| t.name == target.name && r == recipient) {
+                    destinations.push((target, recipient));
+                }
+            }
+        }
+    }
+
+    /// How long a recipient wants between notifications about the same nick, if they said.
+    pub fn frequency(&self, sink: &str, recipient: &str) -> Option<Duration> {
+        self.recipients.iter()
+            .find(|configured| configured.is(sink, recipient))
+            .and_then(|configured| configured.frequency)
+    }
+
+    /// The longest frequency any recipient has of their own.
+    pub fn longest_frequency(&self) -> Option<Duration> {
+        self.recipients.iter().filter_map(|recipient| recipient.frequency).max()
+    }
+
+    /// Whether any recipient is watching for this.
+    pub fn watching(&self, event: &NotificationEvent) -> bool {
+        self.recipients.iter().any(|recipient| recipient.route.matches(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mask::Mask;
+    use notifications::{NotificationEvent, NotificationResult, NotificationSink};
+    use std::time::Duration;
+    use super::{Recipient, Route, Router, Target};
+
+    struct Null;
+
+    impl NotificationSink for Null {
+        fn send(&self, _recipient: &str, _event: &NotificationEvent) -> NotificationResult {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn without_routes_everything_goes_everywhere() {
+        let router = Router::new(targets(), Vec::new());
+        assert_eq!(2, router.destinations(&NotificationEvent::pm("alice", "hi")).len());
+    }
+
+    #[test]
+    fn routes_pick_sinks_by_event_and_nick() {
+        let router = Router::new(targets(), vec![
+            route(&["pm"], &[], &["sms", "email"], None),
+            route(&["join"], &[], &["email"], None),
+            route(&[], &["boss"], &["sms"], Some("+15550000000")),
+        ]);
+
+        let destinations = router.destinations(&NotificationEvent::join("alice", "#hello"));
+        assert_eq!(1, destinations.len());
+        assert_eq!("email", destinations[0].0.name());
+
+        let destinations = router.destinations(&NotificationEvent::pm("boss", "hi"));
+        let recipients: Vec<_> = destinations.iter().map(|&(_, recipient)| recipient).collect();
+        assert_eq!(vec!["+15551234567", "owner@example.org", "+15550000000"], recipients);
+    }
+
+    #[test]
+    fn recipients_get_their_own_on_top_of_routes() {
+        let router = Router::new(targets(), vec![route(&["pm"], &[], &["email"], None)]).with_recipients(vec![Recipient {
+            route: route(&["join"], &["bob*"], &["sms"], Some("+15550000000")),
+            frequency: Some(Duration::from_secs(60)),
+        }]);
+
+        let destinations = router.destinations(&NotificationEvent::join("bobby", "#hello"));
+        assert_eq!(1, destinations.len());
+        assert_eq!("+15550000000", destinations[0].1);
+        assert!(router.destinations(&NotificationEvent::join("alice", "#hello")).is_empty());
+        assert!(!router.watching(&NotificationEvent::join("alice", "#hello")));
+
+        assert_eq!(Some(Duration::from_secs(60)), router.frequency("sms", "+15550000000"));
+        assert_eq!(None, router.frequency("sms", "+15551234567"));
+    }
+
+    #[test]
+    fn what_only_a_recipient_watches_goes_only_to_them() {
+        let router = Router::new(targets(), Vec::new()).with_recipients(vec![Recipient {
+            route: route(&["join"], &["bob*"], &["sms"], Some("+15550000000")),
+            frequency: None,
+        }]);
+
+        let event = NotificationEvent::join("bobby", "#hello");
+        let destinations = router.recipient_destinations(&event);
+        assert_eq!(1, destinations.len());
+        assert_eq!("+15550000000", destinations[0].1);
+
+        // Had the owner been watching too, the default targets would hear about it as well.
+        assert_eq!(3, router.destinations(&event).len());
+        assert!(router.recipient_destinations(&NotificationEvent::join("alice", "#hello")).is_empty());
+    }
+
+    fn targets() -> Vec<Target> {
+        vec![Target::new("sms", Null, "+15551234567"), Target::new("email", Null, "owner@example.org")]
+    }
+
+    fn route(events: &[&str], nicks: &[&str], sinks: &[&str], recipient: Option<&str>) -> Route {
+        Route {
+            events: events.iter().map(|&event| event.to_owned()).collect(),
+            nicks: nicks.iter().map(|&nick| Mask::new(nick)).collect(),
+            sinks: sinks.iter().map(|&sink| sink.to_owned()).collect(),
+            recipient: recipient.map(|recipient| recipient.to_owned()),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, UTC, Weekday};
+use chrono_tz::Tz;
+use config;
+use notifications::{NotificationEvent, NotificationFailure};
+
+/// A stretch of time, recurring daily or on certain days, when a recipient doesn't want to hear
+/// from us.
+pub struct QuietHours {
+    sinks: Vec<String>,
+    recipient: Option<String>,
+    start: u32,
+    end: u32,
+    days: Vec<Weekday>,
+    zone: Tz,
+    hold: bool,
+    urgent: Vec<String>,
+}
+
+impl QuietHours {
+    /// Expects a config that has already been validated.
+    pub fn with_config(config: &config::QuietHours) -> QuietHours {
+        QuietHours {
+            sinks: config.sinks.clone(),
+            recipient: config.recipient.clone(),
+            start: config.start().unwrap_or(0),
+            end: config.end().unwrap_or(0),
+            days: config.days().unwrap_or_default(),
+            zone: config.zone().unwrap_or(Tz::UTC),
+            hold: config.hold,
+            urgent: config.urgent.iter().map(|word| word.to_lowercase()).collect(),
+        }
+    }
+
+    fn applies(&self, sink: &str, recipient: &str) -> bool {
+        (self.sinks.is_empty() || self.sinks.iter().any(|name| name == sink))
+            && self.recipient.as_ref().map_or(true, |name| name == recipient)
+    }
+
+    fn is_urgent(&self, event: &NotificationEvent) -> bool {
+        let body = event.body.as_ref().map_or(String::new(), |body| body.to_lowercase());
+        self.urgent.iter().any(|word| body.contains(&**word))
+    }
+
+    fn quiet_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    /// If `time` falls in quiet hours, when they end.
+    fn ends(&self, time: DateTime<UTC>) -> Option<DateTime<UTC>> {
+        let local = time.with_timezone(&self.zone);
+        let minute = local.hour() * 60 + local.minute();
+        let today = local.date().naive_local();
+        let end_on = |days: i64| {
+            let end = (today + Duration::days(days)).and_hms(self.end / 60, self.end % 60, 0);
+
+            // A clock change can make the end happen twice, in which case it's the first, or skip
+            // it, in which case it's an hour on.
+            self.zone.from_local_datetime(&end).earliest()
+                .or_else(|| self.zone.from_local_datetime(&(end + Duration::hours(1))).earliest())
+                .map_or(time, |end| end.with_timezone(&UTC))
+        };
+
+        if self.start < self.end {
+            if self.quiet_on(local.weekday()) && self.start <= minute && minute < self.end {
+                return Some(end_on(0));
+            }
+        } else {
+            // Overnight, so this could be the tail end of last night's quiet hours.
+            if self.quiet_on(local.weekday()) && minute >= self.start {
+                return Some(end_on(1));
+            }
+            if self.quiet_on(local.weekday().pred()) && minute < self.end {
+                return Some(end_on(0));
+            }
+        }
+
+        None
+    }
+}
+
+/// All the quiet hours there are, checked before anything goes out.
+pub struct Schedule {
+    quiet_hours: Vec<QuietHours>,
+}
+
+impl Schedule {
+    pub fn new(quiet_hours: Vec<QuietHours>) -> Schedule {
+        Schedule {
+            quiet_hours: quiet_hours,
+        }
+    }
+
+    pub fn with_config(config: &[config::QuietHours]) -> Schedule {
+        Schedule::new(config.iter().map(QuietHours::with_config).collect())
+    }
+
+    /// Whether this event may go to this recipient now. If not, says whether it should be held
+    /// and until when.
+    pub fn check(&self, sink: &str, recipient: &str, event: &NotificationEvent) -> Result<(), NotificationFailure> {
+        let now = UTC::now();
+        let mut held_until: Option<DateTime<UTC>> = None;
+
+        for quiet in self.quiet_hours.iter().filter(|quiet| quiet.applies(sink, recipient) && !quiet.is_urgent(event)) {
+            if let Some(end) = quiet.ends(now) {
+                if !quiet.hold {
+                    return Err(NotificationFailure::Quiet(None));
+                }
+                held_until = Some(held_until.map_or(end, |until| if end > until { end } else { until }));
+            }
+        }
+
+        match held_until {
+            None => Ok(()),
+            Some(until) => Err(NotificationFailure::Quiet(Some(until))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, UTC, Weekday};
+    use chrono_tz::America::New_York;
+    use notifications::NotificationEvent;
+    use super::QuietHours;
+
+    #[test]
+    fn overnight_quiet_hours_end_the_next_morning() {
+        let quiet = quiet(23 * 60, 7 * 60, vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri]);
+
+        // Friday night, 23:30 local, is 03:30 UTC on Saturday.
+        let end = quiet.ends(UTC.ymd(2017, 6, 3).and_hms(3, 30, 0));
+        assert_eq!(Some(UTC.ymd(2017, 6, 3).and_hms(11, 0, 0)), end);
+
+        // Nights are quiet by the day they start on, so neither Saturday night nor the early
+        // hours of Monday are.
+        assert_eq!(None, quiet.ends(UTC.ymd(2017, 6, 4).and_hms(3, 30, 0)));
+        assert_eq!(None, quiet.ends(UTC.ymd(2017, 6, 5).and_hms(10, 0, 0)));
+
+        // Tuesday 06:00 local is the tail end of Monday night.
+        assert_eq!(Some(UTC.ymd(2017, 6, 6).and_hms(11, 0, 0)), quiet.ends(UTC.ymd(2017, 6, 6).and_hms(10, 0, 0)));
+    }
+
+    #[test]
+    fn daytime_quiet_hours_stay_within_the_day() {
+        let quiet = quiet(9 * 60, 17 * 60, Vec::new());
+
+        assert!(quiet.ends(UTC.ymd(2017, 6, 1).and_hms(12, 0, 0)).is_none());
+        assert_eq!(Some(UTC.ymd(2017, 6, 1).and_hms(21, 0, 0)), quiet.ends(UTC.ymd(2017, 6, 1).and_hms(15, 0, 0)));
+    }
+
+    #[test]
+    fn quiet_hours_keep_to_local_time_across_daylight_saving() {
+        let (nightly, short) = (quiet(23 * 60, 7 * 60, Vec::new()), quiet(23 * 60, 2 * 60 + 30, Vec::new()));
+
+        // 07:00 is noon UTC in January, but 11:00 in June.
+        assert_eq!(Some(UTC.ymd(2017, 1, 10).and_hms(12, 0, 0)), nightly.ends(UTC.ymd(2017, 1, 10).and_hms(5, 0, 0)));
+        assert_eq!(Some(UTC.ymd(2017, 6, 10).and_hms(11, 0, 0)), nightly.ends(UTC.ymd(2017, 6, 10).and_hms(5, 0, 0)));
+
+        // 02:30 never happens the morning the clocks go forward.
+        assert_eq!(Some(UTC.ymd(2017, 3, 12).and_hms(7, 30, 0)), short.ends(UTC.ymd(2017, 3, 12).and_hms(6, 0, 0)));
+    }
+
+    #[test]
+    fn urgent_words_get_through() {
+        let quiet = quiet(0, 0, Vec::new());
+
+        assert!(quiet.is_urgent(&NotificationEvent::pm("alice", "the server is DOWN")));
+        assert!(!quiet.is_urgent(&NotificationEvent::pm("alice", "hi")));
+    }
+
+    // Quiet hours for someone in New York.
+    fn quiet(start: u32, end: u32, days: Vec<Weekday>) -> QuietHours {
+        QuietHours {
+            sinks: Vec::new(),
+            recipient: None,
+            start: start,
+            end: end,
+            days: days,
+            zone: New_York,
+            hold: true,
+            urgent: vec!["down".to_owned()],
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use notifications::{NotificationEvent, NotificationFailure, NotificationResult, NotificationSink};
+
+pub use rsilio::MessagingService as Sms;
+
+impl NotificationSink for Sms {
+    fn send(&self, recipient: &str, event: &NotificationEvent) -> NotificationResult {
+        match self.send_message(recipient, &event.to_string()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(NotificationFailure::Failure(e.to_string())),
+        }
+    }
+}
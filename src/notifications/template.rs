@@ -0,0 +1,114 @@
+use config;
+use notifications::{EventKind, NotificationEvent};
+use std::collections::HashMap;
+
+/// What a PM says by default, for when there's no template for it but the body has to go.
+const PM: &'static str = "PM from {nick}: {message}";
+
+/// How each kind of event reads, if not the usual way.
+pub struct Templates {
+    templates: HashMap<String, String>,
+    network: String,
+    private: bool,
+}
+
+impl Templates {
+    pub fn new(templates: HashMap<String, String>, network: &str, private: bool) -> Templates {
+        Templates {
+            templates: templates,
+            network: network.to_owned(),
+            private: private,
+        }
+    }
+
+    pub fn with_config(config: &config::Notifications, network: &str) -> Templates {
+        Templates::new(config.templates.clone(), network, config.private)
+    }
+
+    /// The event as it should read for this sink, or just the event if nothing's configured.
+    pub fn apply(&self, event: &NotificationEvent, sink: &str) -> NotificationEvent {
+        // Texts show up on lock screens; what someone said in private shouldn't.
+        let private = self.private && sink == "sms" && event.kind == EventKind::Pm;
+
+        let template = match self.templates.get(event.kind.name()) {
+            Some(template) => &**template,
+            None if private => PM,
+            None => return event.clone(),
+        };
+
+        event.clone().with_text(self.render(template, event, private))
+    }
+
+    /// Fills in `{nick}`, `{channel}`, `{message}`, `{time}` and `{network}`. Anything else in
+    /// braces is left as it is, and so is whatever the filled-in values happen to contain. A
+    /// private `{message}` comes out empty, along with the separator just before it.
+    fn render(&self, template: &str, event: &NotificationEvent, private: bool) -> String {
+        let mut text = String::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            let literal = text.len();
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let end = match rest.find('}') {
+                None => break,
+                Some(end) => end,
+            };
+
+            match &rest[1..end] {
+                "nick" => text.push_str(&event.nick),
+                "channel" => text.push_str(event.channel.as_ref().map_or("", |channel| &**channel)),
+                "message" if private => {
+                    // Only the template's own text, so a nick like `bob-` keeps its dash.
+                    let len = text[literal..].trim_right_matches(|c: char| c.is_whitespace() || c == ':' || c == '-').len();
+                    text.truncate(literal + len);
+                }
+                "message" => text.push_str(event.body.as_ref().map_or("", |body| &**body)),
+                "time" => text.push_str(&event.time.format("%H:%M").to_string()),
+                "network" => text.push_str(&self.network),
+                _ => text.push_str(&rest[..end + 1]),
+            }
+
+            rest = &rest[end + 1..];
+        }
+
+        text.push_str(rest);
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, UTC};
+    use notifications::NotificationEvent;
+    use std::collections::HashMap;
+    use super::Templates;
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let templates = templates(&[("join", "[{network} {time}] {nick} is in {channel} {unknown}")], false);
+        let mut event = NotificationEvent::join("alice", "#hello");
+        event.time = UTC.ymd(2017, 6, 1).and_hms(12, 30, 0);
+
+        assert_eq!("[example.org 12:30] alice is in #hello {unknown}", templates.apply(&event, "sms").to_string());
+        assert_eq!("PM from bob: {nick}", templates.apply(&NotificationEvent::pm("bob", "{nick}"), "sms").to_string());
+    }
+
+    #[test]
+    fn private_messages_stay_off_the_phone() {
+        let templates = templates(&[], true);
+        let event = NotificationEvent::pm("alice", "my password is hunter2");
+
+        assert_eq!("PM from alice", templates.apply(&event, "sms").to_string());
+        assert_eq!("PM from alice: my password is hunter2", templates.apply(&event, "email").to_string());
+
+        let templates = self::templates(&[("pm", "{nick} - {message}")], true);
+        assert_eq!("bob-", templates.apply(&NotificationEvent::pm("bob-", "hi"), "sms").to_string());
+    }
+
+    fn templates(templates: &[(&str, &str)], private: bool) -> Templates {
+        let templates: HashMap<_, _> = templates.iter().map(|&(kind, template)| (kind.to_owned(), template.to_owned())).collect();
+        Templates::new(templates, "example.org", private)
+    }
+}
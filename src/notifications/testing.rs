@@ -0,0 +1,31 @@
+use notifications::{Backoff, Builder, History, NotificationEvent, NotificationResult, NotificationService, NotificationSink, Router, Target};
+use std::env;
+use std::fs;
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// Sends everything by text, to a channel instead of a phone.
+pub struct Recorder {
+    sent: Mutex<Sender<String>>,
+}
+
+impl NotificationSink for Recorder {
+    fn send(&self, _: &str, event: &NotificationEvent) -> NotificationResult {
+        self.sent.lock().unwrap().send(event.to_string()).ok();
+        Ok(())
+    }
+}
+
+/// A service with an `sms` sink that records what it sends, no retries, digests as soon as
+/// they're due, and a history of its own under `name` that starts out empty.
+pub fn recording(name: &str, sent: Sender<String>) -> Builder {
+    let path = env::temp_dir().join(format!("watcher-test-{}.log", name)).to_string_lossy().into_owned();
+    fs::remove_file(&path).ok();
+    fs::remove_file(format!("{}.pending", path)).ok();
+
+    let recorder = Recorder { sent: Mutex::new(sent) };
+    NotificationService::builder(Router::new(vec![Target::new("sms", recorder, "+15551234567")], Vec::new()), History::new(&path))
+        .digest_interval(Duration::from_secs(0))
+        .backoff(Backoff::new(0, Duration::from_secs(1)))
+}
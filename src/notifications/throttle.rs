@@ -0,0 +1,225 @@
+use chrono::{self, DateTime, UTC};
+use config::{self, LimitScope};
+use std::borrow::Borrow;
+use std::time::{Duration, Instant};
+
+pub struct ThrottleWindow {
+    pub period: Duration,
+    pub max_count: usize,
+    pub scope: LimitScope,
+}
+
+impl ThrottleWindow {
+    pub fn with_config(config: &config::Limit) -> ThrottleWindow {
+        ThrottleWindow {
+            period: Duration::from_secs(config.period),
+            max_count: config.count,
+            scope: config.per,
+        }
+    }
+
+    #[cfg(test)]
+    fn can_send<T, I>(&self, items: I) -> bool
+        where
+            T: Borrow<Instant>,
+            I: IntoIterator<Item=T>,
+    {
+        self.reopens(items).is_none()
+    }
+
+    /// When this window will have room again, or `None` if it has room now.
+    fn reopens<T, I>(&self, items: I) -> Option<Instant>
+        where
+            T: Borrow<Instant>,
+            I: IntoIterator<Item=T>,
+    {
+        let mut times: Vec<Instant> = items.into_iter()
+            .map(|item| *item.borrow())
+            .filter(|&time| self.in_window(time))
+            .collect();
+
+        if times.len() < self.max_count {
+            return None;
+        }
+
+        // Room opens up once enough of the oldest sends have aged out.
+        times.sort();
+        Some(times[times.len() - self.max_count] + self.period)
+    }
+
+    fn in_window(&self, time: Instant) -> bool {
+        time.elapsed() < self.period
+    }
+
+    fn counts(&self, sent: &Sent, sink: &str, recipient: &str) -> bool {
+        match self.scope {
+            LimitScope::All => true,
+            LimitScope::Sink => sent.sink == sink,
+            LimitScope::Recipient => sent.recipient == recipient,
+        }
+    }
+}
+
+struct Sent {
+    time: Instant,
+    sink: String,
+    recipient: String,
+}
+
+/// Every notification that actually went out recently, checked against any number of windows.
+pub struct Throttle {
+    windows: Vec<ThrottleWindow>,
+    log: Vec<Sent>,
+}
+
+impl Throttle {
+    pub fn new(windows: Vec<ThrottleWindow>) -> Throttle {
+        Throttle {
+            windows: windows,
+            log: Vec::new(),
+        }
+    }
+
+    /// Without any configured limits, we stick to the old 30 messages every 3 hours.
+    pub fn with_config(limits: &[config::Limit]) -> Throttle {
+        if limits.is_empty() {
+            return Throttle::new(vec![ThrottleWindow {
+                period: Duration::from_secs(60 * 60 * 3),
+                max_count: 30,
+                scope: LimitScope::All,
+            }]);
+        }
+
+        Throttle::new(limits.iter().map(ThrottleWindow::with_config).collect())
+    }
+
+    /// Whether this sink may send to this recipient now. If not, says when it can.
+    /// The longest window; sends further back than this don't count against anything.
+    pub fn longest(&self) -> Duration {
+        self.windows.iter().map(|window| window.period).max().unwrap_or(Duration::from_secs(0))
+    }
+
+    pub fn check(&self, sink: &str, recipient: &str) -> Result<(), DateTime<UTC>> {
+        let reopens = self.windows.iter()
+            .filter_map(|window| {
+                window.reopens(self.log.iter().filter(|sent| window.counts(sent, sink, recipient)).map(|sent| sent.time))
+            })
+            .max();
+
+        match reopens {
+            None => Ok(()),
+            Some(time) => Err(to_utc(time)),
+        }
+    }
+
+    pub fn record(&mut self, sink: &str, recipient: &str, time: Instant) {
+        // Nothing older than the longest window can matter again.
+        let longest = self.longest();
+        self.log.retain(|sent| sent.time.elapsed() < longest);
+
+        self.log.push(Sent {
+            time: time,
+            sink: sink.to_owned(),
+            recipient: recipient.to_owned(),
+        });
+    }
+}
+
+/// Instants can't be shown to people, so this works out the wall-clock time for one.
+fn to_utc(time: Instant) -> DateTime<UTC> {
+    let now = Instant::now();
+    let offset = if time > now {
+        chrono::Duration::from_std(time - now).unwrap_or_else(|_| chrono::Duration::zero())
+    } else {
+        -chrono::Duration::from_std(now - time).unwrap_or_else(|_| chrono::Duration::zero())
+    };
+
+    UTC::now() + offset
+}
+
+#[cfg(test)]
+mod tests {
+    use config::LimitScope;
+    use super::{Throttle, ThrottleWindow};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn messages_allowed_when_threshold_not_passed() {
+        let items = &[Instant::now(), Instant::now(), Instant::now(), Instant::now()];
+        let window = ThrottleWindow {
+            period: Duration::from_secs(60),
+            max_count: 5,
+            scope: LimitScope::All,
+        };
+
+        assert!(window.can_send(items));
+    }
+
+    #[test]
+    fn messages_withheld_when_threshold_passed() {
+        let items = &[Instant::now(), Instant::now(), Instant::now(), Instant::now(), Instant::now()];
+        let window = ThrottleWindow {
+            period: Duration::from_secs(60),
+            max_count: 5,
+            scope: LimitScope::All,
+        };
+
+        assert!(!window.can_send(items));
+    }
+
+    #[test]
+    fn old_messages_do_not_count_against_threshold() {
+        let items = &[Instant::now() - Duration::from_secs(120), Instant::now(), Instant::now(), Instant::now(), Instant::now()];
+        let window = ThrottleWindow {
+            period: Duration::from_secs(60),
+            max_count: 5,
+            scope: LimitScope::All,
+        };
+
+        assert!(window.can_send(items));
+    }
+
+    #[test]
+    fn window_reopens_when_the_oldest_send_ages_out() {
+        let now = Instant::now();
+        let items = &[now - Duration::from_secs(50), now - Duration::from_secs(10)];
+        let window = ThrottleWindow {
+            period: Duration::from_secs(60),
+            max_count: 2,
+            scope: LimitScope::All,
+        };
+
+        assert_eq!(Some(now + Duration::from_secs(10)), window.reopens(items));
+    }
+
+    #[test]
+    fn every_send_counts_even_to_the_same_recipient() {
+        let mut throttle = Throttle::new(vec![window(2, 600, LimitScope::All)]);
+        throttle.record("sms", "+15551234567", Instant::now());
+        throttle.record("sms", "+15551234567", Instant::now());
+
+        assert!(throttle.check("sms", "+15551234567").is_err());
+        assert!(throttle.check("email", "owner@example.org").is_err());
+    }
+
+    #[test]
+    fn scoped_limits_only_count_their_own_sends() {
+        let mut throttle = Throttle::new(vec![window(1, 600, LimitScope::Sink), window(1, 600, LimitScope::Recipient)]);
+        throttle.record("sms", "+15551234567", Instant::now());
+
+        assert!(throttle.check("sms", "+15550000000").is_err());
+        assert!(throttle.check("email", "owner@example.org").is_ok());
+
+        throttle.record("email", "+15550000000", Instant::now());
+        assert!(throttle.check("webhook", "+15550000000").is_err());
+        assert!(throttle.check("webhook", "owner@example.org").is_ok());
+    }
+
+    fn window(max_count: usize, period: u64, scope: LimitScope) -> ThrottleWindow {
+        ThrottleWindow {
+            period: Duration::from_secs(period),
+            max_count: max_count,
+            scope: scope,
+        }
+    }
+}
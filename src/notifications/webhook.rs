@@ -0,0 +1,103 @@
+use config;
+use crypto::{hex, hmac_sha256};
+use hyper::Client;
+use hyper::header::{ContentType, Headers};
+use notifications::{NotificationEvent, NotificationFailure, NotificationResult, NotificationSink};
+use serde_json;
+use std::time::Duration;
+
+#[cfg(feature = "ssl")]
+use hyper::net::HttpsConnector;
+#[cfg(feature = "ssl")]
+use hyper_native_tls::NativeTlsClient;
+
+/// How long a receiver gets to take or answer a request before the send counts as failed, so a
+/// hung one can't hold up the delivery worker.
+const TIMEOUT_SECS: u64 = 30;
+
+/// Posts each notification as JSON to a URL, which is this sink's recipient. Good for ntfy,
+/// Gotify, chat bridges and the like.
+pub struct Webhook {
+    headers: Vec<(String, String)>,
+    secret: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: &'a str,
+    nick: &'a str,
+    channel: Option<&'a str>,
+    message: Option<&'a str>,
+    context: &'a [String],
+    text: String,
+    timestamp: String,
+}
+
+impl Webhook {
+    pub fn with_config(config: &config::Webhook) -> Webhook {
+        Webhook {
+            headers: config.headers.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            secret: config.secret.clone(),
+        }
+    }
+
+    fn post(&self, url: &str, body: &str) -> Result<(), String> {
+        let mut headers = Headers::new();
+        headers.set(ContentType::json());
+
+        for &(ref name, ref value) in &self.headers {
+            headers.set_raw(name.clone(), vec![value.clone().into_bytes()]);
+        }
+
+        // Receivers can recompute this over the raw body to check that we sent it.
+        if let Some(ref secret) = self.secret {
+            let signature = hex(&hmac_sha256(secret.as_bytes(), body.as_bytes()));
+            headers.set_raw("X-Watcher-Signature", vec![format!("sha256={}", signature).into_bytes()]);
+        }
+
+        let response = client()?.post(url).headers(headers).body(body).send().map_err(|e| e.to_string())?;
+        if response.status.is_success() {
+            Ok(())
+        } else {
+            Err(format!("webhook returned {}", response.status))
+        }
+    }
+}
+
+impl NotificationSink for Webhook {
+    fn send(&self, recipient: &str, event: &NotificationEvent) -> NotificationResult {
+        let payload = Payload {
+            event: event.kind.name(),
+            nick: &event.nick,
+            channel: event.channel.as_ref().map(|channel| &**channel),
+            message: event.body.as_ref().map(|body| &**body),
+            context: &event.context,
+            text: event.to_string(),
+            timestamp: event.time.to_rfc3339(),
+        };
+
+        serde_json::to_string(&payload)
+            .map_err(|e| e.to_string())
+            .and_then(|body| self.post(recipient, &body))
+            .map_err(NotificationFailure::Failure)
+    }
+}
+
+fn client() -> Result<Client, String> {
+    let mut client = connect()?;
+    client.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SECS)));
+    client.set_write_timeout(Some(Duration::from_secs(TIMEOUT_SECS)));
+    Ok(client)
+}
+
+#[cfg(feature = "ssl")]
+fn connect() -> Result<Client, String> {
+    NativeTlsClient::new()
+        .map(|tls| Client::with_connector(HttpsConnector::new(tls)))
+        .map_err(|e| format!("unable to set up TLS: {}", e))
+}
+
+#[cfg(not(feature = "ssl"))]
+fn connect() -> Result<Client, String> {
+    Ok(Client::new())
+}
@@ -0,0 +1,261 @@
+use chrono::{DateTime, UTC};
+use config;
+use notifications::{Delivery, NotificationEvent, NotificationFailure, Target};
+use std::cmp;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// One notification on its way to one destination.
+pub struct Job {
+    target: Target,
+    recipient: String,
+    event: NotificationEvent,
+    attempts: u32,
+}
+
+impl Job {
+    pub fn new(target: Target, recipient: &str, event: &NotificationEvent) -> Job {
+        Job {
+            target: target,
+            recipient: recipient.to_owned(),
+            event: event.clone(),
+            attempts: 0,
+        }
+    }
+}
+
+/// How one attempt at a job went, sent back from the worker.
+pub struct Report {
+    pub event: NotificationEvent,
+    pub delivery: Delivery,
+    pub attempts: u32,
+    /// How long until the next try, if there's going to be one.
+    pub retry: Option<Duration>,
+}
+
+impl Report {
+    /// Failed, and that's the end of it.
+    pub fn is_dead(&self) -> bool {
+        self.delivery.result.is_err() && self.retry.is_none()
+    }
+}
+
+/// A notification that never made it, kept around so the owner can see what they missed.
+pub struct DeadLetter {
+    pub time: DateTime<UTC>,
+    pub event: NotificationEvent,
+    pub sink: String,
+    pub recipient: String,
+    pub attempts: u32,
+    pub error: String,
+}
+
+impl DeadLetter {
+    pub fn from_report(report: Report) -> DeadLetter {
+        let error = match report.delivery.result {
+            Err(NotificationFailure::Failure(e)) => e,
+            Err(e) => format!("{:?}", e),
+            Ok(()) => String::new(),
+        };
+
+        DeadLetter {
+            time: UTC::now(),
+            event: report.event,
+            sink: report.delivery.sink,
+            recipient: report.delivery.recipient,
+            attempts: report.attempts,
+            error: error,
+        }
+    }
+}
+
+impl fmt::Display for DeadLetter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} to {} gave up after {} attempts ({}): {}",
+            self.time.format("%F %T"),
+            self.sink,
+            self.recipient,
+            self.attempts,
+            self.error,
+            self.event
+        )
+    }
+}
+
+/// How many times to retry a failed send, and how long to wait before the first retry. Each
+/// retry after that waits twice as long as the one before.
+#[derive(Clone, Copy)]
+pub struct Backoff {
+    retries: u32,
+    delay: Duration,
+}
+
+impl Backoff {
+    pub fn new(retries: u32, delay: Duration) -> Backoff {
+        Backoff {
+            retries: retries,
+            delay: delay,
+        }
+    }
+
+    pub fn with_config(config: &config::Notifications) -> Backoff {
+        Backoff::new(config.retries, Duration::from_secs(config.retry_delay))
+    }
+
+    /// How long to wait after the given number of failed attempts, or `None` to give up.
+    fn after(&self, attempts: u32) -> Option<Duration> {
+        if attempts == 0 || attempts > self.retries {
+            return None;
+        }
+
+        // Nobody is waiting on a retry a few years out, so don't bother doubling past that.
+        Some(self.delay * (1 << cmp::min(attempts - 1, 16)))
+    }
+}
+
+/// Starts the thread that does the actual sending, so a slow sink can't hold up IRC. Jobs go in
+/// one end and reports of every attempt come out the other.
+pub fn spawn(backoff: Backoff) -> (Sender<Job>, Receiver<Report>) {
+    let (jobs, queue) = mpsc::channel();
+    let (reporter, reports) = mpsc::channel();
+
+    thread::spawn(move || work(&queue, &reporter, backoff));
+    (jobs, reports)
+}
+
+fn work(queue: &Receiver<Job>, reports: &Sender<Report>, backoff: Backoff) {
+    let mut retries: Vec<(Instant, Job)> = Vec::new();
+
+    loop {
+        // Wait for new work, but not past the next retry.
+        let received = match retries.iter().map(|&(due, _)| due).min() {
+            None => queue.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            Some(due) => {
+                let now = Instant::now();
+                if due <= now {
+                    Err(RecvTimeoutError::Timeout)
+                } else {
+                    queue.recv_timeout(due - now)
+                }
+            }
+        };
+
+        match received {
+            Ok(job) => deliver(job, reports, backoff, &mut retries),
+            Err(RecvTimeoutError::Timeout) => (),
+
+            // The service is shutting down, and everything it queued has been seen to. Anything
+            // waiting on a retry gets one last go, so it's either sent or reported dead.
+            Err(RecvTimeoutError::Disconnected) => {
+                let last = Backoff::new(0, backoff.delay);
+                let mut none = Vec::new();
+                for (_, job) in retries.drain(..) {
+                    deliver(job, reports, last, &mut none);
+                }
+                return;
+            }
+        }
+
+        let now = Instant::now();
+        let (due, later): (Vec<_>, Vec<_>) = retries.drain(..).partition(|&(time, _)| time <= now);
+        retries = later;
+
+        for (_, job) in due {
+            deliver(job, reports, backoff, &mut retries);
+        }
+    }
+}
+
+fn deliver(mut job: Job, reports: &Sender<Report>, backoff: Backoff, retries: &mut Vec<(Instant, Job)>) {
+    job.attempts += 1;
+    let result = job.target.send(&job.recipient, &job.event);
+
+    // Only an outright failure is worth another go; the sink might be back by then.
+    let retry = match result {
+        Err(NotificationFailure::Failure(_)) => backoff.after(job.attempts),
+        _ => None,
+    };
+
+    let report = Report {
+        event: job.event.clone(),
+        delivery: Delivery {
+            sink: job.target.name().to_owned(),
+            recipient: job.recipient.clone(),
+            result: result,
+        },
+        attempts: job.attempts,
+        retry: retry,
+    };
+    reports.send(report).ok();
+
+    if let Some(delay) = retry {
+        retries.push((Instant::now() + delay, job));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notifications::{NotificationEvent, NotificationFailure, NotificationResult, NotificationSink, Target};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use super::{spawn, Backoff, Job};
+
+    struct Flaky {
+        failures: Arc<Mutex<u32>>,
+    }
+
+    impl NotificationSink for Flaky {
+        fn send(&self, _: &str, _: &NotificationEvent) -> NotificationResult {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures == 0 {
+                Ok(())
+            } else {
+                *failures -= 1;
+                Err(NotificationFailure::Failure(String::from("try again")))
+            }
+        }
+    }
+
+    #[test]
+    fn retries_back_off_exponentially() {
+        let backoff = Backoff::new(3, Duration::from_secs(30));
+
+        assert_eq!(Some(Duration::from_secs(30)), backoff.after(1));
+        assert_eq!(Some(Duration::from_secs(60)), backoff.after(2));
+        assert_eq!(Some(Duration::from_secs(120)), backoff.after(3));
+        assert_eq!(None, backoff.after(4));
+    }
+
+    #[test]
+    fn retries_get_one_last_go_when_the_service_stops() {
+        let (jobs, reports) = spawn(Backoff::new(5, Duration::from_secs(600)));
+        let target = Target::new("sms", Flaky { failures: Arc::new(Mutex::new(5)) }, "+15551234567");
+        jobs.send(Job::new(target, "+15551234567", &NotificationEvent::pm("alice", "hi"))).unwrap();
+
+        assert!(reports.recv().unwrap().retry.is_some());
+        drop(jobs);
+
+        let last = reports.recv().unwrap();
+        assert_eq!(2, last.attempts);
+        assert!(last.is_dead());
+        assert!(reports.recv().is_err());
+    }
+
+    #[test]
+    fn failed_sends_are_retried_until_they_give_up() {
+        let (jobs, reports) = spawn(Backoff::new(1, Duration::from_millis(10)));
+        let target = Target::new("sms", Flaky { failures: Arc::new(Mutex::new(5)) }, "+15551234567");
+        jobs.send(Job::new(target, "+15551234567", &NotificationEvent::pm("alice", "hi"))).unwrap();
+
+        let first = reports.recv().unwrap();
+        assert!(first.retry.is_some() && !first.is_dead());
+
+        let second = reports.recv().unwrap();
+        assert_eq!(2, second.attempts);
+        assert!(second.is_dead());
+    }
+}
@@ -0,0 +1,122 @@
+use greetings::Greeting;
+use mask::Mask;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use toml;
+
+/// Changes made to the bot while it runs, kept in a small TOML file so they survive a restart.
+/// At startup, the contents are merged over what `bot.toml` says.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct State {
+    #[serde(default)]
+    pub admin_added: Vec<Mask>,
+    #[serde(default)]
+    pub admin_removed: Vec<Mask>,
+
+    // Channels joined with `.join`, and configured channels left with `.leave`.
+    #[serde(default)]
+    pub channels: Vec<String>,
+    #[serde(default)]
+    pub channels_left: Vec<String>,
+
+    // Greetings are ordered and removed by position, so once a channel's greetings have been
+    // changed at runtime, its whole list is kept here and replaces the config file's list. TOML
+    // wants tables after plain values, so this stays at the bottom.
+    #[serde(default)]
+    pub greetings: HashMap<String, Vec<Greeting>>,
+}
+
+impl State {
+    /// Reads the state file. A missing file just means nothing has changed yet.
+    pub fn load(path: &str) -> Result<State, String> {
+        let mut file = match File::open(path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(State::default()),
+            Err(e) => return Err(format!("{:?}", e)),
+            Ok(file) => file,
+        };
+
+        let mut buf = String::new();
+        file.read_to_string(&mut buf).map_err(|e| format!("{:?}", e))?;
+        toml::from_str(&buf).map_err(|e| format!("{}", e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let data = toml::to_string(self).map_err(|e| format!("{}", e))?;
+        File::create(path)
+            .and_then(|mut file| file.write_all(data.as_bytes()))
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    /// The admin roster from the config file, with runtime changes applied.
+    pub fn admins(&self, configured: &[Mask]) -> Vec<Mask> {
+        let mut admins: Vec<_> = configured.iter()
+            .filter(|&mask| !self.admin_removed.contains(mask))
+            .cloned()
+            .collect();
+
+        for mask in &self.admin_added {
+            if !admins.contains(mask) {
+                admins.push(mask.clone());
+            }
+        }
+
+        admins
+    }
+
+    pub fn add_admin(&mut self, mask: Mask) {
+        self.admin_removed.retain(|removed| *removed != mask);
+        if !self.admin_added.contains(&mask) {
+            self.admin_added.push(mask);
+        }
+    }
+
+    pub fn join_channel(&mut self, channel: &str) {
+        self.channels_left.retain(|left| left != channel);
+        if !self.channels.iter().any(|joined| joined == channel) {
+            self.channels.push(channel.to_owned());
+        }
+    }
+
+    pub fn leave_channel(&mut self, channel: &str) {
+        self.channels.retain(|joined| joined != channel);
+        if !self.channels_left.iter().any(|left| left == channel) {
+            self.channels_left.push(channel.to_owned());
+        }
+    }
+
+    pub fn remove_admin(&mut self, mask: Mask) {
+        self.admin_added.retain(|added| *added != mask);
+        if !self.admin_removed.contains(&mask) {
+            self.admin_removed.push(mask);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mask::Mask;
+    use super::State;
+
+    #[test]
+    fn runtime_changes_are_merged_over_config() {
+        let configured = vec![Mask::new("alice"), Mask::new("bob")];
+        let mut state = State::default();
+
+        state.remove_admin(Mask::new("bob"));
+        state.add_admin(Mask::new("staff*"));
+
+        assert_eq!(vec![Mask::new("alice"), Mask::new("staff*")], state.admins(&configured));
+    }
+
+    #[test]
+    fn re_adding_a_removed_admin_restores_it() {
+        let configured = vec![Mask::new("alice")];
+        let mut state = State::default();
+
+        state.remove_admin(Mask::new("alice"));
+        state.add_admin(Mask::new("alice"));
+
+        assert_eq!(configured, state.admins(&configured));
+    }
+}
@@ -0,0 +1,43 @@
+use config;
+use greetings::Greeting;
+use mask::{self, Mask};
+use watcher::channel_name;
+
+/// A channel the bot is sitting in, along with the settings that apply there.
+pub struct Channel {
+    pub name: String,
+    pub greetings: Vec<Greeting>,
+    pub watch_list: Vec<Mask>,
+    pub logging: bool,
+    pub auto_op: bool,
+}
+
+impl Channel {
+    /// Settings for a channel we were sent to with `.join` that the config file doesn't know:
+    /// log it, and nothing else. Auto-op goes by joins, and once we're in a second channel
+    /// there's no telling which one a join was in.
+    pub fn new(name: &str) -> Channel {
+        Channel {
+            name: channel_name(name),
+            greetings: Vec::new(),
+            watch_list: Vec::new(),
+            logging: true,
+            auto_op: false,
+        }
+    }
+
+    pub fn with_config(config: &config::Channel) -> Channel {
+        Channel {
+            name: channel_name(&config.name),
+            greetings: config.greetings.clone(),
+            watch_list: config.watch_list.clone(),
+            logging: config.logging,
+            auto_op: config.auto_op,
+        }
+    }
+
+    #[inline]
+    pub fn watching(&self, nick: &str) -> bool {
+        mask::any_match(&self.watch_list, nick)
+    }
+}
@@ -1,7 +1,14 @@
+use auth::{Authorization, Login};
+use command::NewGreeting;
 use dice::Dice;
 use fortune_cookie;
+use greetings::Greeting;
 use icndb::ApiClient;
-use watcher::Watcher;
+use mask::{self, Mask};
+use std::cell::RefMut;
+use std::sync::atomic::Ordering;
+use watcher::{Watcher, channel_name};
+use watcher::channel::Channel;
 use eirsee::message::OutgoingMessage;
 
 const DEFAULT_CHUCK: &'static str = "No one really knows Chuck Norris. Not even Chuck Norris!";
@@ -68,38 +75,207 @@ pub fn roll(sender: String, dice: Vec<Dice>) -> Option<OutgoingMessage> {
     ))
 }
 
-pub fn set_nick(watcher: &Watcher, sender: String, nick: String) -> Option<OutgoingMessage> {
-    if watcher.is_admin(&sender) {
-        Some(OutgoingMessage::Nick(Some(nick)))
-    } else {
-        None
+pub fn authenticate(watcher: &Watcher, sender: String, password: String) -> Option<OutgoingMessage> {
+    let auth = match watcher.auth {
+        Authorization::Password(ref auth) => auth,
+        _ => return Some(OutgoingMessage::to_private(sender, String::from("Password authentication is not enabled."))),
+    };
+
+    let known = mask::any_match(&watcher.admin.borrow(), &sender) || mask::any_match(&watcher.trusted, &sender);
+    let login = if known { auth.login(&sender, &password) } else { Login::Rejected };
+
+    match login {
+        Login::Accepted => {
+            println!("{} has authenticated", sender);
+            Some(OutgoingMessage::to_private(sender, String::from("You are now authenticated.")))
+        }
+        Login::Rejected => {
+            println!("failed authentication attempt by {}", sender);
+            Some(OutgoingMessage::to_private(sender, String::from("Authentication failed.")))
+        }
+        Login::LockedOut => {
+            println!("authentication attempt by {} refused: too many failures", sender);
+            Some(OutgoingMessage::to_private(sender, String::from("Too many failed attempts; try again later.")))
+        }
+    }
+}
+
+pub fn logout(watcher: &Watcher, sender: String) -> Option<OutgoingMessage> {
+    match watcher.auth {
+        Authorization::Password(ref auth) => {
+            auth.logout(&sender);
+            Some(OutgoingMessage::to_private(sender, String::from("Your session has ended.")))
+        }
+        _ => None,
     }
 }
 
-// FIXME: Admin commands like this one need a totally separate path, because checking this here is some 
-// total bullshit.
+pub fn set_nick(nick: String) -> Option<OutgoingMessage> {
+    Some(OutgoingMessage::Nick(Some(nick)))
+}
+
 pub fn set_debug(watcher: &Watcher, sender: String, enabled: bool) -> Option<OutgoingMessage> {
-    if watcher.is_admin(&sender) {
-        watcher.debug.set(enabled);
-        println!("debug mode {}", if enabled { "enabled" } else { "disabled" });
+    watcher.debug.store(enabled, Ordering::Relaxed);
+    println!("debug mode {}", if enabled { "enabled" } else { "disabled" });
 
-        Some(OutgoingMessage::to_private(sender, format!("debug mode set to {}", enabled)))
-    } else {
-        None
+    Some(OutgoingMessage::to_private(sender, format!("debug mode set to {}", enabled)))
+}
+
+pub fn set_topic(topic: String) -> Option<OutgoingMessage> {
+    Some(OutgoingMessage::Topic(topic))
+}
+
+pub fn add_admin(watcher: &Watcher, sender: String, mask: String) -> Option<OutgoingMessage> {
+    let mask = Mask::new(mask);
+
+    // We'd never see a user@host to match it against; see read_config.
+    if !mask.is_nick_pattern() {
+        return Some(OutgoingMessage::to_private(sender, format!("{} isn't a nick pattern; I only ever see nicks", mask)));
+    }
+
+    if watcher.admin.borrow().contains(&mask) {
+        return Some(OutgoingMessage::to_private(sender, format!("{} is already an admin", mask)));
+    }
+
+    watcher.admin.borrow_mut().push(mask.clone());
+    watcher.state.borrow_mut().add_admin(mask.clone());
+    watcher.save_state();
+
+    println!("{} added admin {}", sender, mask);
+    Some(OutgoingMessage::to_private(sender, format!("added admin {}", mask)))
+}
+
+pub fn remove_admin(watcher: &Watcher, sender: String, mask: String) -> Option<OutgoingMessage> {
+    let mask = Mask::new(mask);
+
+    if !watcher.admin.borrow().contains(&mask) {
+        return Some(OutgoingMessage::to_private(sender, format!("{} is not an admin", mask)));
     }
+
+    watcher.admin.borrow_mut().retain(|admin| *admin != mask);
+    watcher.state.borrow_mut().remove_admin(mask.clone());
+    watcher.save_state();
+
+    println!("{} removed admin {}", sender, mask);
+    Some(OutgoingMessage::to_private(sender, format!("removed admin {}", mask)))
 }
 
-// Watcher is unused here because currently we're just setting the topic on the server, but
-// the idea is that we'll be storing the topic string as part of the ServerChannel object in
-// our list of channels, so, for the future, I'm leaving the Watcher object as part of this
-// function signature.
-//
-// Also, see FIXME note in handle_command for what needs to happen here.
-pub fn set_topic(watcher: &Watcher, sender: String, topic: String) -> Option<OutgoingMessage> {
-    if watcher.is_admin(&sender) {
-        Some(OutgoingMessage::Topic(topic))
+pub fn list_admins(watcher: &Watcher, sender: String) -> Option<OutgoingMessage> {
+    let admins: Vec<_> = watcher.admin.borrow().iter().map(|mask| mask.to_string()).collect();
+    Some(OutgoingMessage::to_private(sender, format!("admins: {}", admins.join(", "))))
+}
+
+pub fn add_greeting(watcher: &Watcher, sender: String, channel: String, greeting: NewGreeting) -> Option<OutgoingMessage> {
+    let greeting = match Greeting::new(greeting.passthru, greeting.filter.as_ref().map(|filter| filter.as_str()), greeting.message) {
+        Err(e) => return Some(OutgoingMessage::to_private(sender, format!("bad greeting filter: {}", e))),
+        Ok(greeting) => greeting,
+    };
+
+    let count = match find_channel(watcher, &channel) {
+        None => return None,
+        Some(mut joined) => {
+            joined.greetings.push(greeting);
+            joined.greetings.len()
+        }
+    };
+
+    save_greetings(watcher, &channel);
+    Some(OutgoingMessage::to_private(sender, format!("added greeting {} in {}", count, channel)))
+}
+
+pub fn remove_greeting(watcher: &Watcher, sender: String, channel: String, n: usize) -> Option<OutgoingMessage> {
+    let removed = match find_channel(watcher, &channel) {
+        None => return None,
+        Some(mut joined) => if n > 0 && n <= joined.greetings.len() {
+            Some(joined.greetings.remove(n - 1))
+        } else {
+            None
+        },
+    };
+
+    match removed {
+        None => Some(OutgoingMessage::to_private(sender, format!("no greeting number {} in {}", n, channel))),
+        Some(greeting) => {
+            save_greetings(watcher, &channel);
+            Some(OutgoingMessage::to_private(sender, format!("removed greeting: {}", greeting)))
+        }
+    }
+}
+
+pub fn list_greetings(watcher: &Watcher, sender: String, channel: String) -> Option<OutgoingMessage> {
+    let greetings = watcher.with_channel(&channel, |joined| {
+        joined.greetings.iter()
+            .enumerate()
+            .map(|(idx, greeting)| format!("{}: {}", idx + 1, greeting))
+            .collect::<Vec<_>>()
+    }).unwrap_or_default();
+
+    if greetings.is_empty() {
+        Some(OutgoingMessage::to_private(sender, format!("no greetings in {}", channel)))
     } else {
-        None
+        Some(OutgoingMessage::to_private(sender, greetings.join(" | ")))
+    }
+}
+
+pub fn join_channel(watcher: &Watcher, sender: String, channel: String) -> Option<OutgoingMessage> {
+    let channel = channel_name(&channel);
+
+    if watcher.with_channel(&channel, |_| ()).is_some() {
+        return Some(OutgoingMessage::to_private(sender, format!("already in {}", channel)));
+    }
+
+    watcher.channels.borrow_mut().push(watcher.configured_channel(&channel));
+    watcher.state.borrow_mut().join_channel(&channel);
+    watcher.save_state();
+
+    println!("{} asked me to join {}", sender, channel);
+    Some(OutgoingMessage::Raw(format!("JOIN {}", channel)))
+}
+
+pub fn leave_channel(watcher: &Watcher, sender: String, channel: String) -> Option<OutgoingMessage> {
+    let channel = channel_name(&channel);
+
+    if channel == watcher.home_channel() {
+        return Some(OutgoingMessage::to_private(sender, format!("{} is my home channel; I'm staying", channel)));
+    }
+
+    if watcher.with_channel(&channel, |_| ()).is_none() {
+        return Some(OutgoingMessage::to_private(sender, format!("not in {}", channel)));
+    }
+
+    watcher.channels.borrow_mut().retain(|joined| joined.name != channel);
+    watcher.state.borrow_mut().leave_channel(&channel);
+    watcher.save_state();
+
+    println!("{} asked me to leave {}", sender, channel);
+    Some(OutgoingMessage::Raw(format!("PART {}", channel)))
+}
+
+pub fn kill(watcher: &Watcher, sender: String, reason: Option<String>) -> Option<OutgoingMessage> {
+    println!("{} has requested shutdown", sender);
+
+    // Chat logs are opened and closed on every write, so the state file is all there is to put
+    // away here; main finishes off the notification service on the way out.
+    watcher.save_state();
+    watcher.shutdown.send(reason.unwrap_or_else(|| format!("Killed by {}", sender))).ok();
+
+    None
+}
+
+fn find_channel<'a>(watcher: &'a Watcher, channel: &str) -> Option<RefMut<'a, Channel>> {
+    let channels = watcher.channels.borrow_mut();
+    let idx = channels.iter().position(|joined| joined.name == channel);
+
+    match idx {
+        None => None,
+        Some(idx) => Some(RefMut::map(channels, |channels| &mut channels[idx])),
+    }
+}
+
+fn save_greetings(watcher: &Watcher, channel: &str) {
+    if let Some(greetings) = watcher.with_channel(channel, |joined| joined.greetings.clone()) {
+        watcher.state.borrow_mut().greetings.insert(channel.to_owned(), greetings);
+        watcher.save_state();
     }
 }
 
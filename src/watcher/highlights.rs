@@ -0,0 +1,108 @@
+use config;
+use mask::Mask;
+use regex::Regex;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+/// Watches channel chat for anything the owner would want to hear about.
+pub struct Highlights {
+    patterns: Vec<Regex>,
+    owner: Option<Mask>,
+    context: usize,
+    recent: RefCell<HashMap<String, VecDeque<String>>>,
+}
+
+impl Highlights {
+    /// Patterns have already been checked by the time the config is loaded; any that don't
+    /// compile are skipped.
+    pub fn with_config(config: &config::Highlights, owner: Option<&config::Owner>) -> Highlights {
+        Highlights {
+            patterns: config.patterns.iter().filter_map(|pattern| Regex::new(pattern).ok()).collect(),
+            owner: if config.owner { owner.map(|owner| owner.nick.clone()) } else { None },
+            context: config.context,
+            recent: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Remembers the message for context. If it's a highlight, returns what was said just
+    /// before it, oldest first.
+    pub fn check(&self, sender: &str, channel: &str, message: &str) -> Option<Vec<String>> {
+        let mut recent = self.recent.borrow_mut();
+        let lines = recent.entry(channel.to_owned()).or_insert_with(VecDeque::new);
+
+        let context = if self.matches(sender, message) {
+            Some(lines.iter().cloned().collect())
+        } else {
+            None
+        };
+
+        lines.push_back(format!("{}: {}", sender, message));
+        while lines.len() > self.context {
+            lines.pop_front();
+        }
+
+        context
+    }
+
+    fn matches(&self, sender: &str, message: &str) -> bool {
+        // Nobody needs telling about their own messages.
+        if let Some(ref owner) = self.owner {
+            if owner.matches(sender) {
+                return false;
+            }
+        }
+
+        self.mentions_owner(message) || self.patterns.iter().any(|pattern| pattern.is_match(message))
+    }
+
+    fn mentions_owner(&self, message: &str) -> bool {
+        let owner = match self.owner {
+            None => return false,
+            Some(ref owner) => owner,
+        };
+
+        message.split(|c: char| c.is_whitespace() || c == ',' || c == ':')
+            .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric() && !"[]\\`_^{|}-".contains(c)))
+            .any(|word| !word.is_empty() && owner.matches(word))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{self, Owner};
+    use mask::Mask;
+    use super::Highlights;
+
+    #[test]
+    fn mentions_and_patterns_are_highlights() {
+        let highlights = highlights(&["(?i)deploy"]);
+
+        assert!(highlights.check("bob", "#hello", "alice: are you there?").is_some());
+        assert!(highlights.check("bob", "#hello", "@Alice, look").is_some());
+        assert!(highlights.check("bob", "#hello", "Deploying now").is_some());
+        assert!(highlights.check("bob", "#hello", "malice aforethought").is_none());
+        assert!(highlights.check("alice", "#hello", "I'll deploy later").is_none());
+    }
+
+    #[test]
+    fn context_is_what_came_just_before() {
+        let highlights = highlights(&[]);
+        highlights.check("bob", "#hello", "one");
+        highlights.check("carol", "#rust", "elsewhere");
+        highlights.check("bob", "#hello", "two");
+        highlights.check("carol", "#hello", "three");
+
+        let context = highlights.check("bob", "#hello", "alice?").unwrap();
+        assert_eq!(vec!["bob: two", "carol: three"], context);
+    }
+
+    fn highlights(patterns: &[&str]) -> Highlights {
+        let config = config::Highlights {
+            patterns: patterns.iter().map(|&pattern| pattern.to_owned()).collect(),
+            owner: true,
+            context: 2,
+        };
+
+        Highlights::with_config(&config, Some(&Owner { nick: Mask::new("alice"), active_for: 600 }))
+    }
+}
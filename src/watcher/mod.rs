@@ -1,116 +1,284 @@
+mod channel;
 mod commands;
+mod highlights;
+mod presence;
 mod responder;
+mod ticker;
 
-use command::Command;
+use auth::Authorization;
+use command::{Command, Permission};
+use self::channel::Channel;
+use self::highlights::Highlights;
+use self::presence::Presence;
 use config::{Config, User, Server};
 use eirsee::message::OutgoingMessage;
-use greetings::Greeting;
-use notifications::{NotificationService, Sms};
-use std::cell::Cell;
-use std::collections::HashSet;
+use mask::{self, Mask};
+use notifications::{Backoff, DeadLetter, Email, Filter, History, NotificationEvent, NotificationService, Outcome, Recipient, Route, Router, Schedule, Sms, Target, Templates, Throttle, Webhook};
+use std::cell::RefCell;
+use state::State;
 use std::fs::{File, OpenOptions};
 use std::io::{self, Write};
-use std::sync::RwLock;
-use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
 
 pub struct Watcher {
-    admin: HashSet<String>,
+    admin: RefCell<Vec<Mask>>,
+    trusted: Vec<Mask>,
+    explain_denials: bool,
+    auth: Authorization,
     identity: User,
     server: Server,
-    greetings: Vec<Greeting>,
-    watch_list: HashSet<String>,
-    messaging: RwLock<NotificationService<Sms>>,
+    watch_list: RefCell<Vec<Mask>>,
+    watch_events: Vec<String>,
+    // Shared with the ticker, which sends digests between IRC events.
+    messaging: Arc<Mutex<NotificationService>>,
     log_path: Option<String>,
     admin_mode: bool,
-    debug: Cell<bool>,
+    debug: Arc<AtomicBool>,
+    state: RefCell<State>,
+    state_path: String,
+    shutdown: Sender<String>,
+    channels: RefCell<Vec<Channel>>,
+    presence: Option<Arc<Mutex<Presence>>>,
+    highlights: Highlights,
 }
 
 impl Watcher {
-    pub fn with_config(config: &Config) -> Watcher {
+    pub fn with_config(config: &Config, shutdown: Sender<String>) -> Watcher {
+        let messaging = Arc::new(Mutex::new(create_notification_service(config)));
+        let presence = config.owner.as_ref().map(|owner| Arc::new(Mutex::new(Presence::with_config(owner))));
+        let debug = Arc::new(AtomicBool::new(true));
+        ticker::spawn(messaging.clone(), presence.clone(), debug.clone());
+
         Watcher {
-            admin: config.bot.admin.iter().cloned().collect(),
+            admin: RefCell::new(config.state.admins(&config.bot.admin)),
+            trusted: config.bot.trusted.clone(),
+            explain_denials: config.bot.explain_denials,
+            auth: Authorization::with_config(config.bot.auth.as_ref()),
             identity: config.user.clone(),
             server: config.server.clone(),
-            greetings: config.server.greetings.clone(),
-            watch_list: config.bot.watch_list.iter().cloned().collect(),
-            messaging: RwLock::new(create_notification_service(config)),
+            watch_list: RefCell::new(config.bot.watch_list.clone()),
+            watch_events: config.notifications.watch_events.clone(),
+            messaging: messaging,
             log_path: config.logging.clone().map(|logging| logging.path),
-            // FIXME: this should be set in the config file somewhere.
-            admin_mode: true,
-            debug: Cell::new(true),
+            admin_mode: config.notifications.watch_everyone,
+            debug: debug,
+            state: RefCell::new(config.state.clone()),
+            state_path: config.bot.state.clone(),
+            shutdown: shutdown,
+            channels: RefCell::new(joined_channels(config)),
+            presence: presence,
+            highlights: Highlights::with_config(&config.notifications.highlights, config.owner.as_ref()),
         }
     }
 
-    // TODO: change this so that we're not *just* parsing the command, but also validating the
-    // user's permissions before we actually get to dispatching the command.
+    /// The notification service, for finishing up with at shutdown.
+    pub fn notifications(&self) -> Arc<Mutex<NotificationService>> {
+        self.messaging.clone()
+    }
+
+    /// Parses a command and checks the sender's permissions before anything gets dispatched.
     fn handle_command(&self, sender: String, channel: String, command: String) -> Option<OutgoingMessage> {
-        match command.parse::<Command>() {
-            Ok(command) => match command {
-                Command::Chuck => commands::chuck(sender),
-                Command::Cookie => commands::cookie(sender),
-                Command::ListCommands => commands::list_commands(),
-                Command::Quote(category) => commands::quote(sender, category),
-                Command::QuoteCategories => commands::list_quote_categories(),
-                Command::Roll(dice) => commands::roll(sender, dice),
-
-                // FIXME: Admin commands like these need a separate pathway.
-                // Bot settings
-                Command::SetNick(nick) => commands::set_nick(self, sender, nick),
-                Command::SetDebug(enabled) => commands::set_debug(self, sender, enabled),
-                Command::SetTopic(topic) => commands::set_topic(self, sender, topic),
-
-                // FIXME: In theory, we want to use this to add greetings to the bot's repertoire.
-                Command::SetGreeting(ref _greeting) => None,
-
-                // This one looks odd, but the reason that a lot of these just send back None as their
-                // channel message or whatever is just that they are meant to do work only on the
-                // local machine. This one prints a report of all the messages sent out via SMS; we
-                // really don't care to spew that across the network, do we?
-                Command::ListMessages => {
-                    match self.messaging.read() {
-                        // Still don't think this is actually possible...
-                        Err(_) => panic!("ugh"),
-                        Ok(ref messaging) => list_notifications(messaging.sent()),
-                    }
+        let parsed = match command.parse::<Command>() {
+            Ok(parsed) => parsed,
+            Err(_) => return None,
+        };
+
+        let required = parsed.permission();
+        let identity = self.verified_identity(&sender);
+        let allowed = self.identity_permission(identity.as_ref().map(|identity| &**identity)) >= required;
+        if required > Permission::Public {
+            self.audit(&sender, identity.as_ref().map(|identity| &**identity), &command, allowed);
+        }
+
+        if !allowed {
+            println!("denied {:?} command from {}: {}", required, sender, command);
 
-                    None
-                },
+            return if self.explain_denials {
+                Some(OutgoingMessage::to_private(sender, format!("Sorry, that command requires {:?} permission.", required)))
+            } else {
+                None
+            };
+        }
+
+        self.dispatch(sender, channel, parsed)
+    }
+
+    /// Runs a command. By the time we get here, the sender has already been authorized.
+    fn dispatch(&self, sender: String, channel: String, command: Command) -> Option<OutgoingMessage> {
+        match command {
+            Command::Chuck => commands::chuck(sender),
+            Command::Cookie => commands::cookie(sender),
+            Command::ListCommands => commands::list_commands(),
+            Command::Quote(category) => commands::quote(sender, category),
+            Command::QuoteCategories => commands::list_quote_categories(),
+            Command::Roll(dice) => commands::roll(sender, dice),
 
-                // FIXME: This is meant to cause the bot to exit, but so far all attempts to actually *do* this
-                // have resulted in Very(TM) Bad Things(TM) happening as a result. We were initially using
-                // `irc.close().ok()` from hiirc; I think the way to go at this point is just to actually exit
-                // the application and let destructors close the TCP connection to the server.
-                Command::Kill => None, // irc.close().ok()
+            // Passwords do not belong in the channel; these are only honored over PM.
+            Command::Authenticate(_) | Command::Logout => Some(OutgoingMessage::to_private(
+                sender,
+                String::from("Please authenticate via private message."),
+            )),
 
-                _ => None, // probably an unauthorized command
+            // Bot settings
+            Command::SetNick(nick) => commands::set_nick(nick),
+            Command::SetDebug(enabled) => commands::set_debug(self, sender, enabled),
+            Command::SetTopic(topic) => commands::set_topic(topic),
+
+            // Admin roster
+            Command::AddAdmin(mask) => commands::add_admin(self, sender, mask),
+            Command::RemoveAdmin(mask) => commands::remove_admin(self, sender, mask),
+            Command::ListAdmins => commands::list_admins(self, sender),
+
+            // Greetings
+            Command::AddGreeting(greeting) => commands::add_greeting(self, sender, channel, greeting),
+            Command::RemoveGreeting(n) => commands::remove_greeting(self, sender, channel, n),
+            Command::ListGreetings => commands::list_greetings(self, sender, channel),
+
+            // The report itself goes to the console; we really don't care to spew that across the
+            // network, do we? The sender just hears how much of it there was.
+            Command::ListMessages(args) => {
+                match self.messaging.lock() {
+                    // Still don't think this is actually possible...
+                    Err(_) => panic!("ugh"),
+                    Ok(mut messaging) => {
+                        messaging.collect();
+                        list_notifications(messaging.history(), sender, &args)
+                    }
+                }
             },
+            Command::ListDeadLetters => {
+                match self.messaging.lock() {
+                    Err(_) => panic!("ugh"),
+                    Ok(mut messaging) => {
+                        messaging.collect();
+                        list_dead_letters(messaging.dead_letters(), sender)
+                    }
+                }
+            },
+
+            // Trying to tear down the connection from in here has only ever resulted in Very(TM) Bad
+            // Things(TM), so we just tell main() we're done and let it send the QUIT and exit.
+            Command::Kill(reason) => commands::kill(self, sender, reason),
+
+            // Channels
+            Command::JoinChannel(channel) => commands::join_channel(self, sender, channel),
+            Command::LeaveChannel(channel) => commands::leave_channel(self, sender, channel),
+        }
+    }
 
+    /// Handles the handful of commands accepted over private message. Returns `None` for
+    /// anything else, which is then treated as an ordinary PM.
+    fn handle_private_command(&self, sender: &str, content: &str) -> Option<OutgoingMessage> {
+        match content.parse::<Command>() {
+            Ok(Command::Authenticate(password)) => commands::authenticate(self, sender.to_owned(), password),
+            Ok(Command::Logout) => commands::logout(self, sender.to_owned()),
+
+            // Whatever this is, it may well have a password in it, so it goes no further.
+            _ if is_session_command(content) => {
+                Some(OutgoingMessage::to_private(sender.to_owned(), String::from("Usage: .auth <password>, or .logout")))
+            }
             _ => None,
         }
     }
 
-    fn greet_user(&self, user: String) -> Option<OutgoingMessage> {
+    /// eirsee only knows about the channel it was configured with, so anything meant for one of
+    /// the channels we've joined since has to go out as a raw message.
+    fn reply_in(&self, channel: &str, message: OutgoingMessage) -> OutgoingMessage {
+        if channel == self.home_channel() {
+            return message;
+        }
+
+        match message {
+            OutgoingMessage::ChannelMessage { content } => OutgoingMessage::Raw(format!("PRIVMSG {} :{}", channel, content)),
+            OutgoingMessage::Topic(topic) => OutgoingMessage::Raw(format!("TOPIC {} :{}", channel, topic)),
+            message => message,
+        }
+    }
+
+    /// Sent once we've made it into the home channel, to get back into everything else we were
+    /// in last time.
+    fn rejoin_channels(&self) -> Option<OutgoingMessage> {
+        let home = self.home_channel();
+        let others: Vec<_> = self.channels.borrow().iter()
+            .filter(|channel| channel.name != home)
+            .map(|channel| channel.name.clone())
+            .collect();
+
+        if others.is_empty() {
+            None
+        } else {
+            Some(OutgoingMessage::Raw(format!("JOIN {}", others.join(","))))
+        }
+    }
+
+    #[inline]
+    fn home_channel(&self) -> String {
+        channel_name(self.server.home_channel())
+    }
+
+    /// Looks up the settings for a channel we're in.
+    fn with_channel<T, F: FnOnce(&Channel) -> T>(&self, channel: &str, f: F) -> Option<T> {
+        self.channels.borrow().iter().find(|joined| joined.name == channel).map(f)
+    }
+
+    /// Settings for a channel we're about to join: whatever the config file says, if anything.
+    fn configured_channel(&self, channel: &str) -> Channel {
+        self.server.channels().iter()
+            .map(Channel::with_config)
+            .find(|configured| configured.name == channel)
+            .unwrap_or_else(|| Channel::new(channel))
+    }
+
+    fn greet_user(&self, user: &str, channel: &str) -> Option<OutgoingMessage> {
         use greetings::Greetings;
 
-        let mut greeting = self.greetings.for_user(&user)
-            .fold(String::new(), |mut s, greeting| {
-                s.push_str(&greeting.message(&user));
-                s.push(' ');
-                s
-            });
-
-        match greeting.len() {
-            0 => None,
-            x => {
-                greeting.truncate(x - 1);
-                Some(OutgoingMessage::to_channel(greeting))
+        let greeting = self.with_channel(channel, |channel| {
+            channel.greetings.for_user(user)
+                .fold(String::new(), |mut s, greeting| {
+                    s.push_str(&greeting.message(user));
+                    s.push(' ');
+                    s
+                })
+        });
+
+        match greeting {
+            None => None,
+            Some(ref greeting) if greeting.is_empty() => None,
+            Some(mut greeting) => {
+                let len = greeting.len();
+                greeting.truncate(len - 1);
+                Some(self.reply_in(channel, OutgoingMessage::to_channel(greeting)))
             }
         }
     }
 
+    /// The name this sender has proven is theirs: the nick itself, if anything.
+    fn verified_identity(&self, nick: &str) -> Option<String> {
+        match self.auth {
+            Authorization::Nick => Some(nick.to_owned()),
+            Authorization::Password(ref auth) if auth.is_authenticated(nick) => Some(nick.to_owned()),
+            Authorization::Password(_) => None,
+        }
+    }
+
+    fn permission(&self, nick: &str) -> Permission {
+        self.identity_permission(self.verified_identity(nick).as_ref().map(|identity| &**identity))
+    }
+
+    fn identity_permission(&self, identity: Option<&str>) -> Permission {
+        match identity {
+            Some(identity) if mask::any_match(&self.admin.borrow(), identity) => Permission::Admin,
+            Some(identity) if mask::any_match(&self.trusted, identity) => Permission::Trusted,
+            _ => Permission::Public,
+        }
+    }
+
     #[inline]
     fn is_admin(&self, nick: &str) -> bool {
-        self.admin.contains(nick)
+        self.permission(nick) == Permission::Admin
     }
 
     #[inline]
@@ -120,15 +288,49 @@ impl Watcher {
 
     #[inline]
     fn watching(&self, nick: &str) -> bool {
-        self.watch_list.contains(nick)
+        mask::any_match(&self.watch_list.borrow(), nick)
+    }
+
+    /// Whether the config asks for notifications about this kind of coming or going.
+    #[inline]
+    fn watching_for(&self, event: &NotificationEvent) -> bool {
+        self.watch_events.iter().any(|name| name == event.kind.name())
+    }
+
+    fn with_presence<F: FnOnce(&mut Presence)>(&self, f: F) {
+        if let Some(ref presence) = self.presence {
+            match presence.lock() {
+                Err(_) => panic!("presence lock poisoned"),
+                Ok(mut presence) => f(&mut presence),
+            }
+        }
+    }
+
+    /// Whether the owner is around to see things for themselves, in which case there's no
+    /// point notifying them.
+    fn owner_active(&self) -> bool {
+        let mut active = false;
+        self.with_presence(|presence| active = presence.is_active());
+        active
+    }
+
+    #[inline]
+    fn debug(&self) -> bool {
+        self.debug.load(Ordering::Relaxed)
+    }
+
+    fn save_state(&self) {
+        if let Err(e) = self.state.borrow().save(&self.state_path) {
+            println!("unable to save state to {}: {}", self.state_path, e);
+        }
     }
 
     #[inline]
-    fn logging(&self) -> bool {
-        self.log_path.is_some()
+    fn logging(&self, channel: &str) -> bool {
+        self.log_path.is_some() && self.with_channel(channel, |channel| channel.logging).unwrap_or(false)
     }
 
-    fn open_log(&self) -> Result<File, io::Error> {
+    fn open_log(&self, channel: &str) -> Result<File, io::Error> {
         use chrono::UTC;
 
         // I was going to write a test for this unwrap call, but, honestly, I figure everyone
@@ -137,16 +339,20 @@ impl Watcher {
             io::Error::new(io::ErrorKind::Other, "path not provided")
         })?;
 
-        let path = format!("{}/{}_{}.log", path, UTC::now().format("%F"), self.server.channel.trim_left_matches('#'));
+        let path = format!("{}/{}_{}.log", path, UTC::now().format("%F"), channel.trim_left_matches('#'));
         OpenOptions::new().write(true).create(true).append(true).open(&path)
     }
 
-    fn log(&self, nick: &str, message: &str) {
-        if !self.logging() {
+    fn audit(&self, sender: &str, identity: Option<&str>, command: &str, allowed: bool) {
+        audit(self.log_path.as_ref().map(|path| &**path), sender, identity, command, allowed);
+    }
+
+    fn log(&self, channel: &str, nick: &str, message: &str) {
+        if !self.logging(channel) {
             return;
         }
 
-        match self.open_log() {
+        match self.open_log(channel) {
             Err(e) => println!("{:?}", e),
             Ok(mut file) => {
                 writeln!(file, "{}: {}", nick, message).ok();
@@ -155,16 +361,262 @@ impl Watcher {
     }
 }
 
-fn create_notification_service(config: &Config) -> NotificationService<Sms> {
-    NotificationService::new(Sms::new(&*config.twilio.sid,
-                                      &*config.twilio.token,
-                                      &*config.twilio.number),
-                             &*config.twilio.recipient,
-                             Duration::from_secs(config.bot.message_frequency))
+/// Records a privileged command in `audit.log`, beside the chat logs, along with the identity
+/// its permission was checked against, if the sender had one. Nothing is kept without logs.
+pub fn audit(log_path: Option<&str>, sender: &str, identity: Option<&str>, command: &str, allowed: bool) {
+    use chrono::UTC;
+
+    let path = match log_path {
+        None => return,
+        Some(path) => format!("{}/audit.log", path),
+    };
+
+    let file = OpenOptions::new().write(true).create(true).append(true).open(&path);
+    match file {
+        Err(e) => println!("{:?}", e),
+        Ok(mut file) => {
+            writeln!(
+                file,
+                "{}\t{}\t{}\t{}\t{}",
+                UTC::now().to_rfc3339(),
+                if allowed { "allowed" } else { "denied" },
+                identity.unwrap_or("-"),
+                sender,
+                command
+            ).ok();
+        }
+    }
+}
+
+/// Channel names are case-insensitive, and eirsee sometimes hands them to us without the `#`.
+pub fn channel_name(channel: &str) -> String {
+    let channel = channel.to_lowercase();
+    if channel.starts_with('#') || channel.starts_with('&') {
+        channel
+    } else {
+        format!("#{}", channel)
+    }
+}
+
+/// Whether a PM starts out as `.auth` or `.logout`, however it carries on.
+fn is_session_command(content: &str) -> bool {
+    match content.split_whitespace().next().map(str::to_lowercase) {
+        Some(ref word) => word == ".auth" || word == ".logout",
+        None => false,
+    }
+}
+
+/// Configured channels, plus or minus whatever `.join` and `.leave` have done since. The home
+/// channel is always first and can't be left.
+fn joined_channels(config: &Config) -> Vec<Channel> {
+    let home = channel_name(config.server.home_channel());
+    let mut channels: Vec<_> = config.server.channels().iter()
+        .map(Channel::with_config)
+        .filter(|channel| channel.name == home || !config.state.channels_left.contains(&channel.name))
+        .collect();
+
+    for name in &config.state.channels {
+        if !channels.iter().any(|channel| channel.name == *name) {
+            channels.push(Channel::new(name));
+        }
+    }
+
+    for channel in &mut channels {
+        if let Some(greetings) = config.state.greetings.get(&channel.name) {
+            channel.greetings = greetings.clone();
+        }
+    }
+
+    channels
+}
+
+fn create_notification_service(config: &Config) -> NotificationService {
+    let mut targets = Vec::new();
+
+    if let Some(ref twilio) = config.twilio {
+        let sms = Sms::new(&*twilio.sid, &*twilio.token, &*twilio.number);
+        targets.push(Target::new("sms", sms, &*twilio.recipient));
+    }
+
+    if let Some(ref email) = config.email {
+        targets.push(Target::new("email", Email::with_config(email), &*email.recipient));
+    }
+
+    if let Some(ref webhook) = config.webhook {
+        targets.push(Target::new("webhook", Webhook::with_config(webhook), &*webhook.url));
+    }
+
+    let path = config.notifications.history_path(config.logging.as_ref());
+    let history = History::open(&path).unwrap_or_else(|e| {
+        println!("could not read notification history: {:?}", e);
+        History::new(&path)
+    });
+
+    let routes = config.notifications.routes.iter().map(Route::with_config).collect();
+    let recipients = config.recipients.iter().map(Recipient::with_config).collect();
+    NotificationService::builder(Router::new(targets, routes).with_recipients(recipients), history)
+        .frequency(Duration::from_secs(config.bot.message_frequency))
+        .throttle(Throttle::with_config(&config.notifications.limits))
+        .schedule(Schedule::with_config(&config.notifications.quiet_hours))
+        .digest_interval(Duration::from_secs(config.notifications.digest_interval))
+        .backoff(Backoff::with_config(&config.notifications))
+        .templates(Templates::with_config(&config.notifications, network(&config.server.address)))
+        .build()
 }
 
-fn list_notifications<'a, T: Iterator<Item = (&'a String, &'a Instant)> + 'a>(notifications: T) {
-    for subject in notifications {
-        println!("{:?}", subject);
+/// What `{network}` means in notification templates: the server's host name.
+fn network(address: &str) -> &str {
+    address.split(':').next().unwrap_or(address)
+}
+
+fn list_notifications(history: &History, sender: String, args: &[String]) -> Option<OutgoingMessage> {
+    let records = history.search(&Filter::parse(args));
+    for record in &records {
+        println!("{}", record);
+    }
+
+    let sent = records.iter().filter(|record| record.outcome == Outcome::Sent).count();
+    Some(OutgoingMessage::to_private(
+        sender,
+        format!("{} notifications ({} sent); the full report is on the console", records.len(), sent),
+    ))
+}
+
+fn list_dead_letters(dead: &[DeadLetter], sender: String) -> Option<OutgoingMessage> {
+    for letter in dead {
+        println!("{}", letter);
+    }
+
+    Some(OutgoingMessage::to_private(
+        sender,
+        format!("{} notifications were given up on; the full list is on the console", dead.len()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use auth::hash_password;
+    use config;
+    use eirsee::message::OutgoingMessage;
+    use eirsee::responder::Responder;
+    use mask::Mask;
+    use notifications::testing;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use super::Watcher;
+
+    #[test]
+    fn parts_of_watched_nicks_are_notified() {
+        let (sent, received) = mpsc::channel();
+        let mut watcher = watcher("parts", "watch_everyone = false\nwatch_events = [\"part\"]\n");
+        watcher.messaging = Arc::new(Mutex::new(testing::recording("watcher-parts", sent).build()));
+        *watcher.watch_list.borrow_mut() = vec![Mask::new("bob")];
+
+        watcher.user_part(String::from("carol"));
+        watcher.user_part(String::from("bob"));
+        assert_eq!("bob has left #hello", received.recv_timeout(Duration::from_secs(5)).unwrap());
+        assert!(received.try_recv().is_err());
+    }
+
+    #[test]
+    fn joins_count_in_home_however_many_channels_we_are_in() {
+        let watcher = watcher("channels", "");
+        watcher.handle_command(String::from("boss"), String::from("#hello"), String::from(".join #other"));
+
+        match watcher.user_join(String::from("boss")) {
+            Some(OutgoingMessage::Raw(ref line)) if line == "MODE #hello +o boss" => (),
+            _ => panic!("boss not opped"),
+        }
+    }
+
+    #[test]
+    fn privileged_commands_are_audited() {
+        let watcher = watcher("audit", "");
+        watcher.handle_command(String::from("boss"), String::from("#hello"), String::from(".join #other"));
+        watcher.handle_command(String::from("carol"), String::from("#hello"), String::from(".kill"));
+        watcher.handle_command(String::from("carol"), String::from("#hello"), String::from(".list"));
+
+        let mut log = String::new();
+        let path = format!("{}/audit.log", watcher.log_path.as_ref().unwrap());
+        File::open(&path).unwrap().read_to_string(&mut log).unwrap();
+
+        let entries: Vec<Vec<_>> = log.lines().map(|line| line.split('\t').skip(1).collect()).collect();
+        assert_eq!(vec![vec!["allowed", "boss", "boss", ".join #other"], vec!["denied", "carol", "carol", ".kill"]], entries);
+    }
+
+    #[test]
+    fn passphrases_are_never_forwarded() {
+        let (sent, received) = mpsc::channel();
+        let auth = format!(
+            "[bot.auth]\nmode = \"password\"\nsalt = \"salt\"\nhash = \"{}\"\nsession_timeout = 600\n",
+            hash_password("salt", "correct horse battery")
+        );
+        let mut watcher = watcher("passphrase", &auth);
+        watcher.messaging = Arc::new(Mutex::new(testing::recording("watcher-passphrases", sent).build()));
+
+        assert!(watcher.private_message(String::from("boss"), String::from(".auth correct horse battery")).is_some());
+        assert!(watcher.is_admin("boss"));
+
+        // Nothing that even looks like a login gets sent on anywhere.
+        watcher.private_message(String::from("carol"), String::from(".auth"));
+        watcher.private_message(String::from("carol"), String::from(".AUTH correct horse battery"));
+        watcher.private_message(String::from("carol"), String::from(".logout now"));
+        watcher.private_message(String::from("carol"), String::from("hi"));
+        assert_eq!("PM from carol: hi", received.recv_timeout(Duration::from_secs(5)).unwrap());
+    }
+
+    #[test]
+    fn sessions_end_when_the_nick_comes_back() {
+        let auth = format!(
+            "[bot.auth]\nmode = \"password\"\nsalt = \"salt\"\nhash = \"{}\"\nsession_timeout = 600\n",
+            hash_password("salt", "password")
+        );
+        let watcher = watcher("sessions", &auth);
+
+        watcher.private_message(String::from("boss"), String::from(".auth password"));
+        assert!(watcher.is_admin("boss"));
+
+        watcher.user_join(String::from("boss"));
+        assert!(!watcher.is_admin("boss"));
+    }
+
+    /// A watcher for #hello with `boss` as its admin, keeping its files in a scratch directory
+    /// of its own. `extra` is appended to the config file.
+    fn watcher(name: &str, extra: &str) -> Watcher {
+        let dir = env::temp_dir().join(format!("watcher-test-{}", name));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_string_lossy().into_owned();
+
+        let path = format!("{}/bot.toml", dir);
+        File::create(&path).unwrap().write_all(format!(
+            "[bot]\n\
+             admin = [\"boss\"]\n\
+             message_frequency = 0\n\
+             watch_list = []\n\
+             state = \"{dir}/state.toml\"\n\
+             [server]\n\
+             address = \"irc.example.org:6667\"\n\
+             channel = \"#hello\"\n\
+             [user]\n\
+             nick = \"watcher\"\n\
+             user = \"watcher\"\n\
+             real = \"watcher\"\n\
+             [logging]\n\
+             path = \"{dir}\"\n\
+             [notifications]\n\
+             history = \"{dir}/notifications.log\"\n\
+             {extra}",
+            dir = dir,
+            extra = extra
+        ).as_bytes()).unwrap();
+
+        let config = config::read_config(&path).unwrap_or_else(|e| panic!("{:?}", e));
+        let (shutdown, _) = mpsc::channel();
+        Watcher::with_config(&config, shutdown)
     }
 }
@@ -0,0 +1,92 @@
+use config;
+use mask::Mask;
+use std::time::{Duration, Instant};
+
+/// Keeps an eye on the owner, so we don't text them about things happening right in front of
+/// them.
+///
+/// All we have to go on is what the owner says and their joins and parts, which are taken to be
+/// in the home channel. A quit goes unseen, so it's `active_for` running out that notices the
+/// owner has gone.
+pub struct Presence {
+    owner: Mask,
+    active_for: Duration,
+    channels: Vec<String>,
+    last_spoke: Option<Instant>,
+}
+
+impl Presence {
+    pub fn with_config(config: &config::Owner) -> Presence {
+        Presence {
+            owner: config.nick.clone(),
+            active_for: Duration::from_secs(config.active_for),
+            channels: Vec::new(),
+            last_spoke: None,
+        }
+    }
+
+    fn is_owner(&self, user: &str) -> bool {
+        self.owner.matches(user)
+    }
+
+    /// Seen in at least one of our channels, and said something lately.
+    pub fn is_active(&self) -> bool {
+        !self.channels.is_empty() && self.last_spoke.map_or(false, |spoke| spoke.elapsed() < self.active_for)
+    }
+
+    pub fn spoke(&mut self, user: &str, channel: Option<&str>) {
+        if self.is_owner(user) {
+            self.last_spoke = Some(Instant::now());
+            if let Some(channel) = channel {
+                self.joined(user, channel);
+            }
+        }
+    }
+
+    pub fn joined(&mut self, user: &str, channel: &str) {
+        if self.is_owner(user) && !self.channels.iter().any(|joined| joined == channel) {
+            self.channels.push(channel.to_owned());
+        }
+    }
+
+    pub fn parted(&mut self, user: &str, channel: &str) {
+        if self.is_owner(user) {
+            self.channels.retain(|joined| joined != channel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::Owner;
+    use mask::Mask;
+    use super::Presence;
+
+    #[test]
+    fn owner_is_active_only_when_present_here_and_talking() {
+        let mut presence = Presence::with_config(&Owner { nick: Mask::new("alice"), active_for: 600 });
+        assert!(!presence.is_active());
+
+        // A PM says she's around, but not that she's in any of our channels.
+        presence.spoke("alice", None);
+        assert!(!presence.is_active());
+
+        presence.joined("alice", "#hello");
+        assert!(presence.is_active());
+
+        presence.spoke("bob", Some("#hello"));
+        presence.parted("alice", "#hello");
+        assert!(!presence.is_active());
+
+        presence.spoke("alice", Some("#hello"));
+        assert!(presence.is_active());
+    }
+
+    #[test]
+    fn owner_goes_quiet_after_active_for() {
+        let mut presence = Presence::with_config(&Owner { nick: Mask::new("alice"), active_for: 0 });
+
+        presence.spoke("alice", Some("#hello"));
+        assert!(!presence.is_active());
+    }
+}
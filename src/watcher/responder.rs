@@ -1,29 +1,49 @@
+use auth::Authorization;
 use eirsee::message::OutgoingMessage;
 use eirsee::responder::Responder;
-use notifications::{NotificationResult, NotificationFailure};
-use watcher::Watcher;
+use mask;
+use notifications::{DeliveryResult, NotificationEvent, NotificationFailure};
+use watcher::{Watcher, channel_name};
 
 impl Responder for Watcher {
     fn channel_message(&self, sender: String, channel: String, content: String) -> Option<OutgoingMessage> {
+        let channel = channel_name(&channel);
+        self.with_presence(|presence| presence.spoke(&sender, Some(&*channel)));
+        self.keep_session(&sender);
+
         // Log chat.
-        self.log(&sender, &content);
-        println!("#{} ({}): {}", channel, sender, content);
+        self.log(&channel, &sender, &content);
+        println!("{} ({}): {}", channel, sender, content);
 
-        // Handle public chat commands.
+        // Handle public chat commands, answering in whichever channel they came from.
         if content.starts_with('.') {
-            self.handle_command(sender, channel, content)
+            self.handle_command(sender, channel.clone(), content).map(|message| self.reply_in(&channel, message))
         } else {
+            if let Some(context) = self.highlights.check(&sender, &channel, &content) {
+                self.notify_highlight(&sender, &channel, &content, context);
+            }
             None
         }
     }
 
     fn private_message(&self, sender: String, content: String) -> Option<OutgoingMessage> {
-        match self.messaging.write() {
+        self.with_presence(|presence| presence.spoke(&sender, None));
+        self.keep_session(&sender);
+
+        // Commands like `.auth` are answered directly and must never be forwarded as
+        // notifications--nobody wants their password in a text message.
+        if content.trim_left().starts_with('.') {
+            if let Some(response) = self.handle_private_command(&sender, &content) {
+                return Some(response);
+            }
+        }
+
+        match self.messaging.lock() {
             // No idea under what circumstances we would actually get to this.
             Err(_) => panic!("well, shit"),
 
             Ok(mut messaging) => {
-                if self.debug.get() {
+                if self.debug() {
                     println!("PM from {}: {}", sender, content);
                 }
 
@@ -36,9 +56,15 @@ impl Responder for Watcher {
                 //     return None;
                 // }
 
-                let notification_result = messaging.notify_pm(&sender, &content);
-                if self.debug.get() {
-                    log_message_result(&notification_result);
+                if self.owner_active() {
+                    if self.debug() {
+                        println!("not forwarding PM from {}: owner is here", sender);
+                    }
+                } else {
+                    let notification_result = messaging.notify(&NotificationEvent::pm(&sender, &content));
+                    if self.debug() {
+                        log_message_result(&notification_result);
+                    }
                 }
 
                 Some(OutgoingMessage::to_private(sender, String::from("Sorry, I'm AFK right now. Or a bot. Take your pick.")))
@@ -46,49 +72,150 @@ impl Responder for Watcher {
         }
     }
 
+    // eirsee doesn't tell us which channel a join happened in, so it's taken to be home.
     fn user_join(&self, user: String) -> Option<OutgoingMessage> {
-        // Do not greet yourself.
-        if self.identity.nick == user { return None; }
+        let channel = self.home_channel();
+        self.user_joined(&user, &channel)
+    }
+
+    // Same for parts, and eirsee doesn't pass along the part message either.
+    fn user_part(&self, user: String) -> Option<OutgoingMessage> {
+        let channel = self.home_channel();
+        self.user_parted(&user, &channel, None);
+        None
+    }
+}
+
+impl Watcher {
+    fn notify_highlight(&self, sender: &str, channel: &str, content: &str, context: Vec<String>) {
+        if self.owner_active() {
+            return;
+        }
+
+        match self.messaging.lock() {
+            Err(_) => panic!("fml"),
+            Ok(mut messaging) => {
+                let event = NotificationEvent::mention(mask::nick(sender), channel, content).with_context(context);
+                let message_result = messaging.notify(&event);
+
+                if self.debug() {
+                    log_message_result(&message_result);
+                }
+            }
+        }
+    }
+
+    fn user_joined(&self, user: &str, channel: &str) -> Option<OutgoingMessage> {
+        // Do not greet yourself, but do take the chance to get back into any other channels.
+        if self.identity.nick == user { return self.rejoin_channels(); }
+
+        // Whoever this is, they've just come in, so any session under this nick belonged to
+        // whoever used it before.
+        self.forget_session(user);
+        self.with_presence(|presence| presence.joined(user, channel));
+
+        let (auto_op, watching) = match self.with_channel(channel, |joined| (joined.auto_op, joined.watching(user))) {
+            None => return None,
+            Some(settings) => settings,
+        };
 
         // +o bot admin.
-        if self.admin_mode && self.is_admin(&user) {
+        if auto_op && self.is_admin(user) {
             return Some(OutgoingMessage::Raw(
-                format!("MODE {} +o {}", self.server.channel, user)
+                format!("MODE {} +o {}", channel, user)
             ));
         }
 
-        match self.messaging.write() {
+        self.notify_watched(&NotificationEvent::join(user, channel), watching || self.watching(user));
+
+        // greet user
+        self.greet_user(user, channel)
+    }
+
+    fn user_parted(&self, user: &str, channel: &str, reason: Option<&str>) {
+        self.with_presence(|presence| presence.parted(user, channel));
+        self.forget_session(user);
+
+        if self.identity.nick == user { return; }
+
+        let watching = self.with_channel(channel, |joined| joined.watching(user)).unwrap_or(false);
+        self.notify_watched(&NotificationEvent::part(mask::nick(user), channel, reason), watching || self.watching(user));
+    }
+
+    /// Sessions last as long as their nick keeps turning up.
+    fn keep_session(&self, user: &str) {
+        if let Authorization::Password(ref auth) = self.auth {
+            auth.seen(user);
+        }
+    }
+
+    /// Don't leave a session lying around for whoever picks up the nick next.
+    fn forget_session(&self, user: &str) {
+        match self.auth {
+            Authorization::Password(ref auth) => auth.logout(user),
+            Authorization::Nick => (),
+        }
+    }
+
+    /// Tells the owner someone came or went, provided it's the kind of thing they asked about
+    /// and they aren't around to see it for themselves. Recipients with watch lists of their own
+    /// hear about it too.
+    fn notify_watched(&self, event: &NotificationEvent, watching: bool) {
+        if !self.watching_for(event) {
+            return;
+        }
+
+        if self.owner_active() {
+            if self.debug() {
+                println!("not notifying about {}: owner is here", event);
+            }
+            return;
+        }
+
+        match self.messaging.lock() {
             Err(_) => panic!("fml"),
             Ok(mut messaging) => {
-                if self.admin_mode || self.watching(&user) {
-                    if self.debug.get() {
-                        println!("sending SMS notification for {} in {}", user, self.server.channel);
-                    }
+                // Only the recipients who asked hear about someone the owner isn't watching.
+                let everyone = self.admin_mode || watching;
+                if !(everyone || messaging.watching(event)) {
+                    return;
+                }
 
-                    let message_result = messaging.notify_channel(&user, &self.server.channel);
+                if self.debug() {
+                    println!("sending notification: {}", event);
+                }
 
-                    if self.debug.get() {
-                        log_message_result(&message_result);
-                    }
+                let message_result = if everyone { messaging.notify(event) } else { messaging.notify_recipients(event) };
+
+                if self.debug() {
+                    log_message_result(&message_result);
                 }
             }
         }
-
-        // greet user
-        self.greet_user(user)
     }
+}
 
-    // FIXME: do I care to implement anything here?
-    fn user_part(&self, _user: String) -> Option<OutgoingMessage> {
-        None
+pub fn log_message_result(message_result: &DeliveryResult) {
+    match *message_result {
+        Ok(ref deliveries) if deliveries.is_empty() => println!("notification not routed to any sink"),
+        Ok(ref deliveries) => for delivery in deliveries {
+            match delivery.result {
+                Ok(()) => println!("notification queued for {} to {}", delivery.sink, delivery.recipient),
+                Err(ref e) => println!("notification via {} to {} {}", delivery.sink, delivery.recipient, describe_failure(e)),
+            }
+        },
+        Err(ref e) => println!("notification {}", describe_failure(e)),
     }
 }
 
-fn log_message_result(message_result: &NotificationResult) {
-    match *message_result {
-        Ok(()) => println!("notification sent"),
-        Err(NotificationFailure::RecentlyNotified) => println!("notification withheld: recently notified"),
-        Err(NotificationFailure::Throttled) => println!("notification withheld: too many messages sent recently"),
-        Err(NotificationFailure::Failure(ref e)) => println!("notification failed: {:?}", e),
+pub fn describe_failure(failure: &NotificationFailure) -> String {
+    match *failure {
+        NotificationFailure::RecentlyNotified => String::from("withheld: recently notified"),
+        NotificationFailure::Throttled(until) => {
+            format!("withheld: too many messages sent recently, try again after {}", until.to_rfc3339())
+        }
+        NotificationFailure::Quiet(Some(until)) => format!("held for quiet hours until {}", until.to_rfc3339()),
+        NotificationFailure::Quiet(None) => String::from("dropped for quiet hours"),
+        NotificationFailure::Failure(ref e) => format!("failed: {:?}", e),
     }
 }
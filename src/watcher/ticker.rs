@@ -0,0 +1,79 @@
+use notifications::NotificationService;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use watcher::presence::Presence;
+use watcher::responder::{describe_failure, log_message_result};
+
+/// How often to pick up the delivery worker's reports and look for digests that are due.
+const INTERVAL_SECS: u64 = 15;
+
+/// Keeps notifications moving on their own clock, so the history keeps up with the worker and
+/// a digest goes out when it's due, rather than whenever something next happens on IRC.
+pub fn spawn(messaging: Arc<Mutex<NotificationService>>, presence: Option<Arc<Mutex<Presence>>>, debug: Arc<AtomicBool>) {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(INTERVAL_SECS));
+        tick(&messaging, presence.as_ref().map(|presence| &**presence), debug.load(Ordering::Relaxed));
+    });
+}
+
+/// Records what the worker has done since last time, and sends whatever digests are due.
+/// Digests wait while the owner is around.
+pub fn tick(messaging: &Mutex<NotificationService>, presence: Option<&Mutex<Presence>>, debug: bool) {
+    let active = presence.map_or(false, |presence| match presence.lock() {
+        Err(_) => panic!("presence lock poisoned"),
+        Ok(presence) => presence.is_active(),
+    });
+
+    match messaging.lock() {
+        Err(_) => panic!("fml"),
+        Ok(mut messaging) => {
+            let delivered = messaging.collect();
+            if debug {
+                for delivery in &delivered {
+                    match delivery.result {
+                        Ok(()) => println!("notification sent via {} to {}", delivery.sink, delivery.recipient),
+                        Err(ref e) => println!("notification via {} to {} {}, retrying", delivery.sink, delivery.recipient, describe_failure(e)),
+                    }
+                }
+            }
+
+            if active {
+                return;
+            }
+
+            if let Some(result) = messaging.flush() {
+                if debug {
+                    log_message_result(&result);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notifications::{testing, NotificationEvent};
+    use std::sync::Mutex;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use super::tick;
+
+    #[test]
+    fn digests_go_out_without_waiting_for_irc() {
+        let (sent, received) = mpsc::channel();
+        let messaging = Mutex::new(testing::recording("ticker", sent).frequency(Duration::from_secs(600)).build());
+
+        {
+            let mut messaging = messaging.lock().unwrap();
+            messaging.notify(&NotificationEvent::pm("alice", "hi")).unwrap();
+            assert!(messaging.notify(&NotificationEvent::pm("alice", "still there?")).is_err());
+        }
+        assert_eq!("PM from alice: hi", received.recv_timeout(Duration::from_secs(5)).unwrap());
+
+        tick(&messaging, None, false);
+        let digest = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(digest.starts_with("missed: "), "{}", digest);
+    }
+}